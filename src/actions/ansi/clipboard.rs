@@ -0,0 +1,83 @@
+// ANSI specific functions for the system clipboard (OSC 52). Writing works
+// over SSH too, since the terminal on the user's side does the copying.
+// Reading is often disabled or needs the user's permission; the contents
+// arrive as an `InputEvent::Clipboard`.
+
+use std::fmt::{ Result, Write };
+use crate::common::{ base64, enums::ClipboardTarget };
+use super::command::Command;
+
+
+// The most bytes of content to encode at a time when a payload is sent in
+// pieces. A multiple of 3, so the pieces' base64 joins up without padding.
+pub const CHUNK_SIZE: usize = 3 * 4096;
+
+// The most bytes of content `Term::set_clipboard` sends. Many terminals
+// drop OSC 52 sequences past about 100 kB, which is what this much content
+// comes to in base64.
+pub const MAX_CONTENT: usize = 75_000;
+
+fn target_code(target: ClipboardTarget) -> char {
+    match target {
+        ClipboardTarget::Clipboard => 'c',
+        ClipboardTarget::Primary => 'p',
+    }
+}
+
+
+// A clipboard write in pieces: `BeginCopy`, any number of `CopyData` with
+// the content split on `CHUNK_SIZE` boundaries, then `EndCopy`.
+pub struct BeginCopy(pub ClipboardTarget);
+
+impl Command for BeginCopy {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B]52;{};", target_code(self.0))
+    }
+}
+
+pub struct CopyData<'a>(pub &'a [u8]);
+
+impl Command for CopyData<'_> {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        base64::encode_into(self.0, out)
+    }
+}
+
+pub struct EndCopy;
+
+impl Command for EndCopy {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B\\")
+    }
+}
+
+pub struct SetClipboard<'a>(pub ClipboardTarget, pub &'a str);
+
+impl Command for SetClipboard<'_> {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        BeginCopy(self.0).write_ansi(out)?;
+        CopyData(self.1.as_bytes()).write_ansi(out)?;
+        EndCopy.write_ansi(out)
+    }
+}
+
+pub struct RequestClipboard(pub ClipboardTarget);
+
+impl Command for RequestClipboard {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B]52;{};?\x1B\\", target_code(self.0))
+    }
+}
+
+impl_display!(
+    BeginCopy, CopyData<'_>, EndCopy, SetClipboard<'_>, RequestClipboard,
+);
+
+
+pub fn set_clipboard(target: ClipboardTarget, content: &str) -> String {
+    SetClipboard(target, content).to_string()
+}
+
+pub fn request_clipboard(target: ClipboardTarget) -> String {
+    RequestClipboard(target).to_string()
+}
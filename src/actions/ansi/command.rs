@@ -0,0 +1,62 @@
+// A command is an ANSI action that writes its escape sequence straight into
+// a caller supplied writer. Building a full frame out of commands therefore
+// needs no allocation per action, unlike the `String` returning functions
+// (which are kept as thin wrappers around the commands).
+//
+// Commands write into any `fmt::Write` (eg. a `String` or a `Formatter`)
+// with `write_ansi`, or into any `io::Write` (eg. a locked stdout or a
+// `Vec<u8>`) with `write_io`. Every command also implements `Display`.
+
+use std::fmt;
+use std::io;
+
+
+pub trait Command {
+    fn write_ansi(&self, out: &mut dyn fmt::Write) -> fmt::Result;
+
+    fn write_io(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        let mut adapter = IoAdapter { inner: out, error: None };
+        match self.write_ansi(&mut adapter) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(adapter.error.unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Other, "Error formatting the command.")
+            })),
+        }
+    }
+}
+
+impl<C: Command + ?Sized> Command for &C {
+    fn write_ansi(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+        (**self).write_ansi(out)
+    }
+}
+
+
+// Forwards formatted output to an `io::Write`, keeping the underlying I/O
+// error (`fmt::Error` carries no information).
+struct IoAdapter<'a> {
+    inner: &'a mut dyn io::Write,
+    error: Option<io::Error>,
+}
+
+impl fmt::Write for IoAdapter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+
+// Implements `Display` for command types by way of `write_ansi`.
+macro_rules! impl_display {
+    ($($t:ty),* $(,)?) => {
+        $(impl std::fmt::Display for $t {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                $crate::actions::ansi::command::Command::write_ansi(self, f)
+            }
+        })*
+    }
+}
@@ -1,34 +1,213 @@
 // ANSI specific functions for controlling the terminal cursor.
 
+use std::fmt::{ Result, Write };
+use crate::common::enums::{ Color, CursorShape };
+use super::command::Command;
+use super::style::write_color_spec;
+
+
+pub struct Goto(pub i16, pub i16);
+
+impl Command for Goto {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{};{}H", self.1 + 1, self.0 + 1)
+    }
+}
+
+pub struct MoveUp(pub i16);
+
+impl Command for MoveUp {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}A", self.0)
+    }
+}
+
+pub struct MoveRight(pub i16);
+
+impl Command for MoveRight {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}C", self.0)
+    }
+}
+
+pub struct MoveDown(pub i16);
+
+impl Command for MoveDown {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}B", self.0)
+    }
+}
+
+pub struct MoveLeft(pub i16);
+
+impl Command for MoveLeft {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}D", self.0)
+    }
+}
+
+pub struct HideCursor;
+
+impl Command for HideCursor {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?25l")
+    }
+}
+
+pub struct ShowCursor;
+
+impl Command for ShowCursor {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?25h")
+    }
+}
+
+pub struct Pos;
+
+impl Command for Pos {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[6n")
+    }
+}
+
+// DECSC: saves the cursor position along with the current text attributes
+// and character set.
+pub struct MarkPos;
+
+impl Command for MarkPos {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B7")
+    }
+}
+
+// DECRC: restores what the last `MarkPos` saved.
+pub struct LoadPos;
+
+impl Command for LoadPos {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B8")
+    }
+}
+
+// DECSCUSR
+pub struct SetShape(pub CursorShape);
+
+impl Command for SetShape {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        let n = match self.0 {
+            CursorShape::Default => 0,
+            CursorShape::BlinkingBlock => 1,
+            CursorShape::SteadyBlock => 2,
+            CursorShape::BlinkingUnderline => 3,
+            CursorShape::SteadyUnderline => 4,
+            CursorShape::BlinkingBar => 5,
+            CursorShape::SteadyBar => 6,
+        };
+        write!(out, "\x1B[{} q", n)
+    }
+}
+
+pub struct EnableBlink;
+
+impl Command for EnableBlink {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?12h")
+    }
+}
+
+pub struct DisableBlink;
+
+impl Command for DisableBlink {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?12l")
+    }
+}
+
+// OSC 12. `Color::Reset` goes back to the terminal's own cursor color.
+pub struct SetColor(pub Color);
+
+impl Command for SetColor {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        if self.0 == Color::Reset {
+            return ResetColor.write_ansi(out);
+        }
+        out.write_str("\x1B]12;")?;
+        write_color_spec(self.0, out)?;
+        out.write_str("\x1B\\")
+    }
+}
+
+pub struct ResetColor;
+
+impl Command for ResetColor {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B]112\x1B\\")
+    }
+}
+
+impl_display!(
+    Goto, MoveUp, MoveRight, MoveDown, MoveLeft,
+    HideCursor, ShowCursor, Pos, MarkPos, LoadPos,
+    SetShape, EnableBlink, DisableBlink, SetColor, ResetColor,
+);
+
 
 pub fn goto(col: i16, row: i16) -> String {
-    format!("\x1B[{};{}H", row + 1, col + 1)
+    Goto(col, row).to_string()
 }
 
 pub fn move_up(n: i16) -> String {
-    format!("\x1B[{}A", n)
+    MoveUp(n).to_string()
 }
 
 pub fn move_right(n: i16) -> String {
-    format!("\x1B[{}C", n)
+    MoveRight(n).to_string()
 }
 
 pub fn move_down(n: i16) -> String {
-    format!("\x1B[{}B", n)
+    MoveDown(n).to_string()
 }
 
 pub fn move_left(n: i16) -> String {
-    format!("\x1B[{}D", n)
+    MoveLeft(n).to_string()
 }
 
 pub fn hide_cursor() -> String {
-    "\x1B[?25l".to_string()
+    HideCursor.to_string()
 }
 
 pub fn show_cursor() -> String {
-    "\x1B[?25h".to_string()
+    ShowCursor.to_string()
 }
 
 pub fn pos() -> String {
-    "\x1B[6n".to_string()
+    Pos.to_string()
+}
+
+pub fn mark_pos() -> String {
+    MarkPos.to_string()
+}
+
+pub fn load_pos() -> String {
+    LoadPos.to_string()
+}
+
+pub fn set_shape(shape: CursorShape) -> String {
+    SetShape(shape).to_string()
+}
+
+pub fn enable_blink() -> String {
+    EnableBlink.to_string()
+}
+
+pub fn disable_blink() -> String {
+    DisableBlink.to_string()
+}
+
+pub fn set_color(color: Color) -> String {
+    SetColor(color).to_string()
+}
+
+pub fn reset_color() -> String {
+    ResetColor.to_string()
 }
@@ -1,10 +1,17 @@
 // ANSI specific functions.
 
+#[macro_use]
+pub mod command;
 pub mod cursor;
 pub mod screen;
 pub mod style;
 pub mod output;
 pub mod mouse;
+pub mod query;
+pub mod clipboard;
+pub mod palette;
+
+pub use command::Command;
 
 
 // #[cfg(test)]
@@ -1,19 +1,32 @@
 // ANSI specific functions that enable/disable mouse mode.
 
+use std::fmt::{ Result, Write };
+use super::command::Command;
+
+
+pub struct EnableMouse;
+
+impl Command for EnableMouse {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?1000h\x1B[?1002h\x1B[?1015h\x1B[?1006h")
+    }
+}
+
+pub struct DisableMouse;
+
+impl Command for DisableMouse {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?1006l\x1B[?1015l\x1B[?1002l\x1B[?1000l")
+    }
+}
+
+impl_display!(EnableMouse, DisableMouse);
+
+
 pub fn enable_mouse_mode() -> String {
-    format!("{}h{}h{}h{}h",
-        "\x1B[?1000",
-        "\x1B[?1002",
-        "\x1B[?1015",
-        "\x1B[?1006"
-    )
+    EnableMouse.to_string()
 }
 
 pub fn disable_mouse_mode() -> String {
-    format!("{}l{}l{}l{}l",
-        "\x1B[?1006",
-        "\x1B[?1015",
-        "\x1B[?1002",
-        "\x1B[?1000"
-    )
+    DisableMouse.to_string()
 }
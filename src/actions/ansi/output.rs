@@ -1,32 +1,118 @@
 // ANSI specific methods to print to the terminal.
 
 use std::io::Result;
-use std::io::{ stdout, BufWriter, Write };
+use std::io::{ stdout, Write };
+use super::command::Command;
 
 pub fn prints(content: &str) -> Result<()> {
     let output = stdout();
-    let lock = output.lock();
-    let mut outbuf = BufWriter::new(lock);
-    outbuf.write_all(content.as_bytes())
-    // .expect("I/O error on write");
+    let mut lock = output.lock();
+    lock.write_all(content.as_bytes())
 }
 
-pub fn flush() -> Result<()> {
+// Writes a command straight into stdout without building a `String`.
+pub fn queue(command: &dyn Command) -> Result<()> {
     let output = stdout();
-    let lock = output.lock();
-    let mut outbuf = BufWriter::new(lock);
-    outbuf.flush()
-    // .expect("I/O error on flush");
+    let mut lock = output.lock();
+    command.write_io(&mut lock)
+}
+
+pub fn flush() -> Result<()> {
+    stdout().flush()
 }
 
 pub fn printf(content: &str) -> Result<()> {
     let output = stdout();
-    let lock = output.lock();
-    let mut outbuf = BufWriter::new(lock);
-    outbuf.write_all(content.as_bytes())?;
-    // .expect("I/O error on write");
-    outbuf.flush()
-    // .expect("I/O error on flush");
+    let mut lock = output.lock();
+    lock.write_all(content.as_bytes())?;
+    lock.flush()
+}
+
+
+// The default capacity of an `OutputBuffer`, which is also the size at
+// which it flushes itself unless configured otherwise.
+pub const BUFFER_CAPACITY: usize = 64 * 1024;
+
+// Collects output (eg. a whole frame) so that it reaches the terminal in a
+// single write when flushed, rather than piecemeal as it is produced.
+pub struct OutputBuffer {
+    data: Vec<u8>,
+    auto_flush: usize,
+}
+
+impl Default for OutputBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::with_capacity(BUFFER_CAPACITY)
+    }
+
+    // The auto flush threshold starts out equal to `capacity`.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { data: Vec::with_capacity(capacity), auto_flush: capacity }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    // Makes room for at least `capacity` bytes in total.
+    pub fn reserve(&mut self, capacity: usize) {
+        self.data.reserve(capacity.saturating_sub(self.data.len()));
+    }
+
+    // Once the buffer holds `threshold` bytes or more it should be flushed
+    // (see `needs_flush`). `usize::MAX` turns automatic flushing off.
+    pub fn set_auto_flush(&mut self, threshold: usize) {
+        self.auto_flush = threshold;
+    }
+
+    pub fn auto_flush(&self) -> usize {
+        self.auto_flush
+    }
+
+    pub fn needs_flush(&self) -> bool {
+        self.data.len() >= self.auto_flush
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn push_str(&mut self, content: &str) {
+        self.data.extend_from_slice(content.as_bytes());
+    }
+
+    pub fn queue(&mut self, command: &dyn Command) {
+        // Writing into a `Vec<u8>` cannot fail.
+        let _ = command.write_io(&mut self.data);
+    }
+
+    // Discards everything that has not been flushed yet.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    // Sends the buffered output to `out` in a single write. The buffer is
+    // emptied even if the write fails, so that a broken frame is not sent
+    // again.
+    pub fn flush_into(&mut self, out: &mut dyn Write) -> Result<()> {
+        let written = match self.data.is_empty() {
+            true => Ok(()),
+            false => out.write_all(&self.data),
+        };
+        self.data.clear();
+        written?;
+        out.flush()
+    }
 }
 
 
@@ -43,7 +129,7 @@ pub fn printf(content: &str) -> Result<()> {
 // the user or program to move it to where they would like the cursor to be.
 
 #[cfg(unix)]
-use std::{ mem, io::Error };
+use std::{ mem, io::Error, os::unix::io::RawFd };
 
 #[cfg(unix)]
 use libc::{
@@ -54,9 +140,15 @@ use libc::{
 
 #[cfg(unix)]
 pub fn get_mode() -> Result<Termios> {
+    get_mode_of(STDIN_FILENO)
+}
+
+// Same as `get_mode`, for the terminal behind `fd`.
+#[cfg(unix)]
+pub fn get_mode_of(fd: RawFd) -> Result<Termios> {
     unsafe {
         let mut termios = mem::zeroed();
-        if tcgetattr(STDIN_FILENO, &mut termios) == -1 {
+        if tcgetattr(fd, &mut termios) == -1 {
             Err(Error::last_os_error())
         } else {
             Ok(termios)
@@ -67,23 +159,33 @@ pub fn get_mode() -> Result<Termios> {
 /// This function enables raw mode in the current screen.
 #[cfg(unix)]
 pub fn enable_raw() -> Result<()> {
+    enable_raw_of(STDIN_FILENO)
+}
+
+#[cfg(unix)]
+pub fn enable_raw_of(fd: RawFd) -> Result<()> {
     unsafe {
         // Get the current terminal attrs.
-        let mut termios = get_mode()?;
+        let mut termios = get_mode_of(fd)?;
         // Apply the raw attr to the current terminal attrs.
         // There is no effect until a subsequent call to tcsetattr().
         // https://www.mkssoftware.com/docs/man3/cfmakeraw.3.asp
         cfmakeraw(&mut termios);
         // Set the current terminal with raw-enabled attrs.
         // unwrap(tcsetattr(0, 0, &termios)).and(Ok(()))
-        set_mode(&termios)?;
+        set_mode_of(fd, &termios)?;
         Ok(())
     }
 }
 
 #[cfg(unix)]
 pub fn set_mode(termios: &Termios) -> Result<()> {
-    if unsafe { tcsetattr(STDIN_FILENO, TCSANOW, termios) } == -1 {
+    set_mode_of(STDIN_FILENO, termios)
+}
+
+#[cfg(unix)]
+pub fn set_mode_of(fd: RawFd, termios: &Termios) -> Result<()> {
+    if unsafe { tcsetattr(fd, TCSANOW, termios) } == -1 {
         Err(Error::last_os_error())
     } else {
         Ok(())
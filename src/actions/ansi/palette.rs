@@ -0,0 +1,66 @@
+// ANSI functions that change the colors the terminal draws with: the
+// entries of the 256 color palette (OSC 4) and the default text and
+// background colors (OSC 10 and 11). Ask for the current ones with
+// `query::RequestColor`.
+
+use std::fmt::{ Result, Write };
+use crate::common::enums::{ Color, ColorSlot };
+use super::command::Command;
+use super::style::write_color_spec;
+
+
+// Setting a slot to `Color::Reset` gives it back the terminal's own color.
+pub struct SetColor(pub ColorSlot, pub Color);
+
+impl Command for SetColor {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        if self.1 == Color::Reset {
+            return ResetColor(self.0).write_ansi(out);
+        }
+        match self.0 {
+            ColorSlot::Foreground => out.write_str("\x1B]10;")?,
+            ColorSlot::Background => out.write_str("\x1B]11;")?,
+            ColorSlot::Cursor => out.write_str("\x1B]12;")?,
+            ColorSlot::Palette(n) => write!(out, "\x1B]4;{};", n)?,
+        }
+        write_color_spec(self.1, out)?;
+        out.write_str("\x1B\\")
+    }
+}
+
+pub struct ResetColor(pub ColorSlot);
+
+impl Command for ResetColor {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        match self.0 {
+            ColorSlot::Foreground => out.write_str("\x1B]110\x1B\\"),
+            ColorSlot::Background => out.write_str("\x1B]111\x1B\\"),
+            ColorSlot::Cursor => out.write_str("\x1B]112\x1B\\"),
+            ColorSlot::Palette(n) => write!(out, "\x1B]104;{}\x1B\\", n),
+        }
+    }
+}
+
+// Resets every entry of the palette at once.
+pub struct ResetPalette;
+
+impl Command for ResetPalette {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B]104\x1B\\")
+    }
+}
+
+impl_display!(SetColor, ResetColor, ResetPalette);
+
+
+pub fn set_color(slot: ColorSlot, color: Color) -> String {
+    SetColor(slot, color).to_string()
+}
+
+pub fn reset_color(slot: ColorSlot) -> String {
+    ResetColor(slot).to_string()
+}
+
+pub fn reset_palette() -> String {
+    ResetPalette.to_string()
+}
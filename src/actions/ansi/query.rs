@@ -0,0 +1,86 @@
+// ANSI specific functions that ask the terminal for information. The answers
+// arrive as input events (see `crate::parser::unix`).
+
+use std::fmt::{ Result, Write };
+use crate::common::enums::{ ColorSlot, Query };
+use super::command::Command;
+
+
+pub struct DeviceAttrs;
+
+impl Command for DeviceAttrs {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[c")
+    }
+}
+
+pub struct RequestMode(pub u16);
+
+impl Command for RequestMode {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[?{}$p", self.0)
+    }
+}
+
+pub struct RequestColor(pub ColorSlot);
+
+impl Command for RequestColor {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        match self.0 {
+            ColorSlot::Foreground => out.write_str("\x1B]10;?\x1B\\"),
+            ColorSlot::Background => out.write_str("\x1B]11;?\x1B\\"),
+            ColorSlot::Cursor => out.write_str("\x1B]12;?\x1B\\"),
+            ColorSlot::Palette(n) => write!(out, "\x1B]4;{};?\x1B\\", n),
+        }
+    }
+}
+
+pub struct RequestTermCap<'a>(pub &'a str);
+
+impl Command for RequestTermCap<'_> {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1BP+q")?;
+        for b in self.0.bytes() { write!(out, "{:02X}", b)? }
+        out.write_str("\x1B\\")
+    }
+}
+
+pub struct Request<'a>(pub &'a Query);
+
+impl Command for Request<'_> {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        match self.0 {
+            Query::CursorPos => super::cursor::Pos.write_ansi(out),
+            Query::DeviceAttrs => DeviceAttrs.write_ansi(out),
+            Query::Mode(m) => RequestMode(*m).write_ansi(out),
+            Query::Color(slot) => RequestColor(*slot).write_ansi(out),
+            Query::TermCap(name) => RequestTermCap(name).write_ansi(out),
+        }
+    }
+}
+
+impl_display!(
+    DeviceAttrs, RequestMode, RequestColor,
+    RequestTermCap<'_>, Request<'_>,
+);
+
+
+pub fn device_attrs() -> String {
+    DeviceAttrs.to_string()
+}
+
+pub fn mode(mode: u16) -> String {
+    RequestMode(mode).to_string()
+}
+
+pub fn color(slot: ColorSlot) -> String {
+    RequestColor(slot).to_string()
+}
+
+pub fn termcap(name: &str) -> String {
+    RequestTermCap(name).to_string()
+}
+
+pub fn request(query: &Query) -> String {
+    Request(query).to_string()
+}
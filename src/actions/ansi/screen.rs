@@ -1,40 +1,329 @@
 // ANSI functions for configuring the terminal size and clearing the screen.
 
+use std::fmt::{ Result, Write };
 use crate::common::enums::Clear;
+use super::command::Command;
 
 
-pub fn clear(clr: Clear) -> String {
-    match clr {
-        Clear::All => {
-            "\x1B[2J".to_string()
-        }
-        Clear::CursorDn => {
-            "\x1B[J".to_string()
-        }
-        Clear::CursorUp => {
-            "\x1B[1J".to_string()
-        }
-        Clear::CurrentLn => {
-            "\x1B[2K".to_string()
-        }
-        Clear::NewLn => {
-            "\x1B[K".to_string()
-        }
+pub struct ClearScreen(pub Clear);
+
+impl Command for ClearScreen {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str(match self.0 {
+            Clear::All => "\x1B[2J",
+            Clear::CursorDn => "\x1B[J",
+            Clear::CursorUp => "\x1B[1J",
+            Clear::CurrentLn => "\x1B[2K",
+            Clear::NewLn => "\x1B[K",
+        })
+    }
+}
+
+pub struct Resize(pub i16, pub i16);
+
+impl Command for Resize {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[8;{};{}t", self.1, self.0)
+    }
+}
+
+pub struct EnableAlt;
+
+impl Command for EnableAlt {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?1049h")
     }
 }
 
+pub struct DisableAlt;
+
+impl Command for DisableAlt {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?1049l")
+    }
+}
+
+// DECSTBM: limits scrolling to the rows from `top` to `bottom` (inclusive,
+// counted from 0). The terminal also moves the cursor home.
+pub struct SetScrollRegion(pub i16, pub i16);
+
+impl Command for SetScrollRegion {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{};{}r", self.0 + 1, self.1 + 1)
+    }
+}
+
+pub struct ResetScrollRegion;
+
+impl Command for ResetScrollRegion {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[r")
+    }
+}
+
+// Scrolls the content of the region up by n lines (SU); blank lines come
+// in at the bottom.
+pub struct ScrollUp(pub i16);
+
+impl Command for ScrollUp {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}S", self.0)
+    }
+}
+
+// Scrolls the content of the region down by n lines (SD); blank lines come
+// in at the top.
+pub struct ScrollDown(pub i16);
+
+impl Command for ScrollDown {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}T", self.0)
+    }
+}
+
+// Moves the cursor up a line, scrolling the region down if the cursor is
+// already on its top line.
+pub struct ReverseIndex;
+
+impl Command for ReverseIndex {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1BM")
+    }
+}
+
+// Editing commands. They act at the cursor and leave it where it is.
+
+// Inserts n blank lines at the cursor row (IL), pushing the lines below it
+// down. Lines pushed past the bottom of the scroll region are lost.
+pub struct InsertLines(pub i16);
+
+impl Command for InsertLines {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}L", self.0)
+    }
+}
+
+// Deletes n lines from the cursor row down (DL), pulling the lines below
+// up and leaving blank lines at the bottom of the scroll region.
+pub struct DeleteLines(pub i16);
+
+impl Command for DeleteLines {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}M", self.0)
+    }
+}
+
+// Inserts n blank cells at the cursor (ICH), pushing the rest of the line
+// right. Cells pushed past the right edge are lost.
+pub struct InsertChars(pub i16);
+
+impl Command for InsertChars {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}@", self.0)
+    }
+}
+
+// Deletes n cells at the cursor (DCH), pulling the rest of the line left.
+pub struct DeleteChars(pub i16);
+
+impl Command for DeleteChars {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}P", self.0)
+    }
+}
+
+// Blanks n cells from the cursor on (ECH) without moving anything.
+pub struct EraseChars(pub i16);
+
+impl Command for EraseChars {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write!(out, "\x1B[{}X", self.0)
+    }
+}
+
+// OSC 2: sets the window title.
+pub struct SetTitle<'a>(pub &'a str);
+
+impl Command for SetTitle<'_> {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write_osc_text(2, self.0, out)
+    }
+}
+
+// OSC 1: sets the icon name (the tab title, in many terminals).
+pub struct SetIconName<'a>(pub &'a str);
+
+impl Command for SetIconName<'_> {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write_osc_text(1, self.0, out)
+    }
+}
+
+// OSC 0: sets both the window title and the icon name.
+pub struct SetTitleAndIcon<'a>(pub &'a str);
+
+impl Command for SetTitleAndIcon<'_> {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        write_osc_text(0, self.0, out)
+    }
+}
+
+// XTWINOPS 22: saves the window title and icon name on the terminal's
+// title stack.
+pub struct PushTitle;
+
+impl Command for PushTitle {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[22;0t")
+    }
+}
+
+// XTWINOPS 23: restores the last saved window title and icon name.
+pub struct PopTitle;
+
+impl Command for PopTitle {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[23;0t")
+    }
+}
+
+// Control characters would end the sequence early, so they are left out.
+fn write_osc_text(n: u8, text: &str, out: &mut dyn Write) -> Result {
+    write!(out, "\x1B]{};", n)?;
+    for ch in text.chars().filter(|ch| !ch.is_control()) {
+        out.write_char(ch)?;
+    }
+    out.write_str("\x1B\\")
+}
+
+// Synchronized output (DEC mode 2026): the terminal holds off repainting
+// between the two, so a frame written in several pieces shows up at once.
+pub struct BeginSync;
+
+impl Command for BeginSync {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?2026h")
+    }
+}
+
+pub struct EndSync;
+
+impl Command for EndSync {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[?2026l")
+    }
+}
+
+impl_display!(
+    ClearScreen, Resize, EnableAlt, DisableAlt,
+    SetScrollRegion, ResetScrollRegion, ScrollUp, ScrollDown, ReverseIndex,
+    InsertLines, DeleteLines, InsertChars, DeleteChars, EraseChars,
+    SetTitle<'_>, SetIconName<'_>, SetTitleAndIcon<'_>, PushTitle, PopTitle,
+    BeginSync, EndSync,
+);
+
+
+pub fn clear(clr: Clear) -> String {
+    ClearScreen(clr).to_string()
+}
+
 pub fn resize(w: i16, h: i16) -> String {
-    format!("\x1B[8;{};{}t", h, w)
+    Resize(w, h).to_string()
 }
 
 
 pub fn enable_alt() -> String {
-    "\x1B[?1049h".to_string()
+    EnableAlt.to_string()
 }
 
 
 pub fn disable_alt() -> String {
-    "\x1B[?1049l".to_string()
+    DisableAlt.to_string()
+}
+
+
+pub fn set_scroll_region(top: i16, bottom: i16) -> String {
+    SetScrollRegion(top, bottom).to_string()
+}
+
+
+pub fn reset_scroll_region() -> String {
+    ResetScrollRegion.to_string()
+}
+
+
+pub fn scroll_up(n: i16) -> String {
+    ScrollUp(n).to_string()
+}
+
+
+pub fn scroll_down(n: i16) -> String {
+    ScrollDown(n).to_string()
+}
+
+
+pub fn reverse_index() -> String {
+    ReverseIndex.to_string()
+}
+
+
+pub fn insert_lines(n: i16) -> String {
+    InsertLines(n).to_string()
+}
+
+
+pub fn delete_lines(n: i16) -> String {
+    DeleteLines(n).to_string()
+}
+
+
+pub fn insert_chars(n: i16) -> String {
+    InsertChars(n).to_string()
+}
+
+
+pub fn delete_chars(n: i16) -> String {
+    DeleteChars(n).to_string()
+}
+
+
+pub fn erase_chars(n: i16) -> String {
+    EraseChars(n).to_string()
+}
+
+
+pub fn set_title(title: &str) -> String {
+    SetTitle(title).to_string()
+}
+
+
+pub fn set_icon_name(name: &str) -> String {
+    SetIconName(name).to_string()
+}
+
+
+pub fn set_title_and_icon(title: &str) -> String {
+    SetTitleAndIcon(title).to_string()
+}
+
+
+pub fn push_title() -> String {
+    PushTitle.to_string()
+}
+
+
+pub fn pop_title() -> String {
+    PopTitle.to_string()
+}
+
+
+pub fn begin_sync() -> String {
+    BeginSync.to_string()
+}
+
+
+pub fn end_sync() -> String {
+    EndSync.to_string()
 }
 
 #[cfg(unix)]
@@ -42,6 +331,12 @@ use libc::{ioctl, winsize, STDOUT_FILENO, TIOCGWINSZ};
 
 #[cfg(unix)]
 pub fn size() -> std::io::Result<(i16, i16)> {
+    size_of(STDOUT_FILENO)
+}
+
+// Same as `size`, for the terminal behind `fd`.
+#[cfg(unix)]
+pub fn size_of(fd: std::os::unix::io::RawFd) -> std::io::Result<(i16, i16)> {
     // Reference source:
     // http://rosettacode.org/wiki/Terminal_control/Dimensions#Library:_BSD_libc
     let mut size = winsize {
@@ -50,7 +345,7 @@ pub fn size() -> std::io::Result<(i16, i16)> {
         ws_xpixel: 0,
         ws_ypixel: 0,
     };
-    let r = unsafe { ioctl(STDOUT_FILENO, TIOCGWINSZ.into(), &mut size) };
+    let r = unsafe { ioctl(fd, TIOCGWINSZ.into(), &mut size) };
 
     if r == 0 {
         Ok((size.ws_col as i16, size.ws_row as i16))
@@ -1,77 +1,268 @@
 // Ansi specific functions to colorize and format text in the terminal.
 
-use crate::common::enums::{Style, Color, Effect::*};
+use std::fmt::{ Result, Write };
+use crate::common::enums::{Style, Color, ColorEncoding, Effect, TextStyle};
+use super::command::Command;
 
 
-pub fn reset() -> String {
-    "\x1B[0m".to_string()
+pub struct ResetStyle;
+
+impl Command for ResetStyle {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B[0m")
+    }
 }
 
-pub fn set_style(style: Style) -> String {
-    match style {
-        Style::Fg(c) => {
-            if c == Color::Reset {
-                return format!("\x1B[{}m", "39");
+pub struct SetStyle(pub Style);
+
+impl Command for SetStyle {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        SetStyleAs(self.0, ColorEncoding::Indexed).write_ansi(out)
+    }
+}
+
+// Same as `SetStyle`, naming the basic colors as the encoding says.
+pub struct SetStyleAs(pub Style, pub ColorEncoding);
+
+impl Command for SetStyleAs {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        let (color, base) = match self.0 {
+            Style::Fg(c) => (c, 38),
+            Style::Bg(c) => (c, 48),
+            Style::Ul(c) => (c, 58),
+            Style::Fx(f) => {
+                for fx in &Effect::ALL {
+                    if (f & *fx as u32) != 0 {
+                        write!(out, "\x1B[{}m", fx.sgr())?;
+                    }
+                }
+                return Ok(());
             }
-            format!("\x1B[38;{}m", String::from(c))
-        }
-        Style::Bg(c) => {
-            if c == Color::Reset {
-                return format!("\x1B[{}m", "49");
+        };
+        out.write_str("\x1B[")?;
+        write_color_param(color, base, self.1, out)?;
+        out.write_str("m")
+    }
+}
+
+pub struct SetStyles(pub Color, pub Color, pub u32);
+
+impl Command for SetStyles {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        SetStyle(Style::Fg(self.0)).write_ansi(out)?;
+        SetStyle(Style::Bg(self.1)).write_ansi(out)?;
+        SetStyle(Style::Fx(self.2)).write_ansi(out)
+    }
+}
+
+// OSC 8: text printed until `EndLink` links to `url`. Terminals join
+// separate runs of text that share an `id` into one link (eg. a path that
+// is wrapped over two lines).
+pub struct BeginLink<'a> {
+    pub url: &'a str,
+    pub id: Option<&'a str>,
+}
+
+impl Command for BeginLink<'_> {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B]8;")?;
+        if let Some(id) = self.id {
+            // `:` and `;` separate the parameters.
+            out.write_str("id=")?;
+            for ch in id.chars().filter(|ch| !is_osc_special(*ch)) {
+                out.write_char(ch)?;
             }
-            format!("\x1B[48;{}m", String::from(c))
         }
-        Style::Fx(f) => {
-            let fxs = [Reset, Bold, Dim, Underline, Reverse, Hide];
-            let mut csi = String::with_capacity(12);
-            for fx in &fxs {
-                if (f & *fx as u32) != 0 {
-                    let value = (*fx as u32 >> 9).trailing_zeros() as u8;
-                    csi.push_str(&format!("\x1B[{}m", value));
-                } else {
-                    csi.push_str("");
-                }
-            }
-            csi
+        out.write_char(';')?;
+        for ch in self.url.chars().filter(|ch| !ch.is_control()) {
+            out.write_char(ch)?;
+        }
+        out.write_str("\x1B\\")
+    }
+}
+
+fn is_osc_special(ch: char) -> bool {
+    ch.is_control() || ch == ':' || ch == ';'
+}
+
+pub struct EndLink;
+
+impl Command for EndLink {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        out.write_str("\x1B]8;;\x1B\\")
+    }
+}
+
+// Changes the style from the first to the second in as few bytes as it can.
+// Effects that are no longer wanted are turned off with their own off codes
+// when that is shorter than resetting everything and starting over.
+pub struct Transition(pub TextStyle, pub TextStyle, pub ColorEncoding);
+
+impl Command for Transition {
+    fn write_ansi(&self, out: &mut dyn Write) -> Result {
+        let (from, to, encoding) = (&self.0, &self.1, self.2);
+        let diff = diff_params(from, to, encoding);
+        if diff.is_empty() { return Ok(()) }
+        let fresh = diff_params(&TextStyle::default(), to, encoding);
+        match fresh.len() + 2 < diff.len() {
+            true if fresh.is_empty() => out.write_str("\x1B[0m"),
+            true => write!(out, "\x1B[0;{}m", fresh),
+            false => write!(out, "\x1B[{}m", diff),
         }
     }
 }
 
+// The off code of each group of effects. Turning one effect of a group off
+// turns off the whole group.
+const OFF_CODES: [(&str, &[Effect]); 8] = [
+    ("22", &[Effect::Bold, Effect::Dim]),
+    ("23", &[Effect::Italic]),
+    ("24", &[
+        Effect::Underline, Effect::DoubleUnderline, Effect::CurlyUnderline,
+        Effect::DottedUnderline, Effect::DashedUnderline,
+    ]),
+    ("25", &[Effect::SlowBlink, Effect::RapidBlink]),
+    ("27", &[Effect::Reverse]),
+    ("28", &[Effect::Hide]),
+    ("29", &[Effect::Strikethrough]),
+    ("55", &[Effect::Overline]),
+];
+
+// The SGR parameters, joined by `;`, that take `from` to `to`.
+fn diff_params(
+    from: &TextStyle, to: &TextStyle, encoding: ColorEncoding
+) -> String {
+    let mut params: Vec<String> = Vec::new();
+
+    let reset = Effect::Reset as u32;
+    let (was, wants) = (from.fx & !reset, to.fx & !reset);
+    let mut added = wants & !was;
+    for (code, group) in OFF_CODES.iter() {
+        let mask = group.iter().fold(0, |acc, fx| acc | *fx as u32);
+        if was & !wants & mask != 0 {
+            params.push(code.to_string());
+            // Put back what the off code took along with it.
+            added |= wants & mask;
+        }
+    }
+    for fx in Effect::ALL.iter() {
+        if added & *fx as u32 != 0 {
+            params.push(fx.sgr().to_string());
+        }
+    }
+
+    let colors = [
+        (from.fg, to.fg, 38), (from.bg, to.bg, 48), (from.ul, to.ul, 58),
+    ];
+    for (old, new, base) in colors.iter() {
+        if old == new { continue }
+        let mut param = String::new();
+        // Writing into a `String` cannot fail.
+        let _ = write_color_param(*new, *base, encoding, &mut param);
+        params.push(param);
+    }
+    params.join(";")
+}
+
+impl_display!(
+    ResetStyle, SetStyle, SetStyleAs, SetStyles,
+    BeginLink<'_>, EndLink, Transition,
+);
+
+
+pub fn reset() -> String {
+    ResetStyle.to_string()
+}
+
+pub fn set_style(style: Style) -> String {
+    SetStyle(style).to_string()
+}
+
+pub fn set_style_as(style: Style, encoding: ColorEncoding) -> String {
+    SetStyleAs(style, encoding).to_string()
+}
+
 pub fn set_styles(fg: Color, bg: Color, fx: u32) -> String {
-    format!(
-        "{fg}{bg}{fx}",
-        fg=set_style(Style::Fg(fg)),
-        bg=set_style(Style::Bg(bg)),
-        fx=set_style(Style::Fx(fx)))
+    SetStyles(fg, bg, fx).to_string()
 }
 
+pub fn transition(
+    from: TextStyle, to: TextStyle, encoding: ColorEncoding
+) -> String {
+    Transition(from, to, encoding).to_string()
+}
+
+pub fn begin_link(url: &str, id: Option<&str>) -> String {
+    BeginLink { url, id }.to_string()
+}
+
+pub fn end_link() -> String {
+    EndLink.to_string()
+}
+
+
+// Writes the whole SGR parameter that sets `color` for the layer whose
+// extended color code is `base` (38 for the text, 48 for the background and
+// 58 for underlines). Underline colors have no legacy codes.
+pub fn write_color_param(
+    color: Color, base: u8, encoding: ColorEncoding, out: &mut dyn Write
+) -> Result {
+    if color == Color::Reset {
+        return write!(out, "{}", base + 1);
+    }
+    let basic = color.ansi_value().filter(|n| *n < 16);
+    if let (ColorEncoding::Legacy, Some(n), 38 | 48) = (encoding, basic, base) {
+        // 30 + n (or 90 + n - 8 when bright) for text, 10 more for the
+        // background.
+        let code = match n < 8 {
+            true => 30 + n,
+            false => 90 + n - 8,
+        };
+        return write!(out, "{}", code + base - 38);
+    }
+    write!(out, "{};", base)?;
+    write_color(color, out)
+}
+
+// Writes the parameters that follow `38;` or `48;` for a color.
+pub fn write_color(src: Color, out: &mut dyn Write) -> Result {
+    match src {
+        Color::Black => out.write_str("5;0"),
+        Color::DarkGrey => out.write_str("5;8"),
+        Color::Red => out.write_str("5;9"),
+        Color::DarkRed => out.write_str("5;1"),
+        Color::Green => out.write_str("5;10"),
+        Color::DarkGreen => out.write_str("5;2"),
+        Color::Yellow => out.write_str("5;11"),
+        Color::DarkYellow => out.write_str("5;3"),
+        Color::Blue => out.write_str("5;12"),
+        Color::DarkBlue => out.write_str("5;4"),
+        Color::Magenta => out.write_str("5;13"),
+        Color::DarkMagenta => out.write_str("5;5"),
+        Color::Cyan => out.write_str("5;14"),
+        Color::DarkCyan => out.write_str("5;6"),
+        Color::White => out.write_str("5;15"),
+        Color::Grey => out.write_str("5;7"),
+        Color::Rgb { r, g, b } => write!(out, "2;{};{};{}", r, g, b),
+        Color::AnsiValue(val) => write!(out, "5;{}", val),
+        Color::Reset => Ok(()),
+    }
+}
 
 impl From<Color> for String {
     fn from(src: Color) -> Self {
-        match src {
-            Color::Black => String::from("5;0"),
-            Color::DarkGrey => String::from("5;8"),
-            Color::Red => String::from("5;9"),
-            Color::DarkRed => String::from("5;1"),
-            Color::Green => String::from("5;10"),
-            Color::DarkGreen => String::from("5;2"),
-            Color::Yellow => String::from("5;11"),
-            Color::DarkYellow => String::from("5;3"),
-            Color::Blue => String::from("5;12"),
-            Color::DarkBlue => String::from("5;4"),
-            Color::Magenta => String::from("5;13"),
-            Color::DarkMagenta => String::from("5;5"),
-            Color::Cyan => String::from("5;14"),
-            Color::DarkCyan => String::from("5;6"),
-            Color::White => String::from("5;15"),
-            Color::Grey => String::from("5;7"),
-            Color::Rgb { r, g, b } => {
-                String::from(&format!("2;{};{};{}", r, g, b))
-            },
-            Color::AnsiValue(val) => {
-                String::from(&format!("5;{}", val))
-            }
-            Color::Reset => String::from(""),
-        }
+        let mut s = String::new();
+        // Writing into a `String` cannot fail.
+        let _ = write_color(src, &mut s);
+        s
+    }
+}
+
+// Writes a color the way OSC sequences take it (`rgb:rr/gg/bb`). Writes
+// nothing for `Color::Reset`.
+pub fn write_color_spec(src: Color, out: &mut dyn Write) -> Result {
+    match src.to_rgb() {
+        Some((r, g, b)) => write!(out, "rgb:{:02x}/{:02x}/{:02x}", r, g, b),
+        None => Ok(()),
     }
 }
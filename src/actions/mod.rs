@@ -1,4 +1,7 @@
 // This module exposes terminal functions for Ansi and Windows Console.
 pub mod ansi;
 #[cfg(windows)]
-pub mod wincon;
\ No newline at end of file
+pub mod wincon;
+
+#[cfg(test)]
+mod tests;
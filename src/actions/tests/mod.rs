@@ -0,0 +1,173 @@
+use super::ansi::{ Command, cursor, screen, style, query, output, clipboard,
+    palette };
+use crate::common::enums::{
+    Clear, ClipboardTarget, Color, ColorEncoding, ColorSlot, CursorShape,
+    Effect, Query, Style, TextStyle,
+};
+
+
+#[test]
+fn test_ansi_commands() {
+    // Commands write the same sequences as the String wrappers.
+    assert_eq!(cursor::goto(16, 75), "\x1B[76;17H");
+    assert_eq!(cursor::Goto(16, 75).to_string(), "\x1B[76;17H");
+    assert_eq!(cursor::mark_pos(), "\x1B7");
+    assert_eq!(cursor::LoadPos.to_string(), "\x1B8");
+    assert_eq!(cursor::set_shape(CursorShape::SteadyBar), "\x1B[6 q");
+    assert_eq!(cursor::enable_blink(), "\x1B[?12h");
+    assert_eq!(
+        cursor::set_color(Color::Rgb { r: 255, g: 16, b: 0 }),
+        "\x1B]12;rgb:ff/10/00\x1B\\");
+    assert_eq!(
+        cursor::set_color(Color::DarkBlue), "\x1B]12;rgb:00/00/ee\x1B\\");
+    assert_eq!(cursor::set_color(Color::Reset), "\x1B]112\x1B\\");
+    assert_eq!(screen::clear(Clear::CurrentLn), "\x1B[2K");
+    assert_eq!(screen::set_scroll_region(2, 20), "\x1B[3;21r");
+    assert_eq!(screen::scroll_up(3), "\x1B[3S");
+    assert_eq!(screen::ScrollDown(1).to_string(), "\x1B[1T");
+    assert_eq!(screen::insert_lines(2), "\x1B[2L");
+    assert_eq!(screen::set_title("a\x07b\x1Bc"), "\x1B]2;abc\x1B\\");
+    assert_eq!(screen::SetIconName("vim").to_string(), "\x1B]1;vim\x1B\\");
+    assert_eq!(screen::push_title(), "\x1B[22;0t");
+    assert_eq!(screen::delete_chars(4), "\x1B[4P");
+    assert_eq!(screen::EraseChars(5).to_string(), "\x1B[5X");
+    assert_eq!(query::termcap("RGB"), "\x1BP+q524742\x1B\\");
+    assert_eq!(
+        query::request(&Query::Mode(2026)), "\x1B[?2026$p");
+
+    let fx = Effect::Bold | Effect::Underline;
+    assert_eq!(
+        style::set_styles(Color::Yellow, Color::Rgb { r: 1, g: 2, b: 3 }, fx),
+        "\x1B[38;5;11m\x1B[48;2;1;2;3m\x1B[1m\x1B[4m");
+    assert_eq!(style::set_style(Style::Fg(Color::Reset)), "\x1B[39m");
+    assert_eq!(style::set_style(Style::Ul(Color::Red)), "\x1B[58;5;9m");
+    assert_eq!(
+        style::set_style(Style::Ul(Color::Rgb { r: 9, g: 8, b: 7 })),
+        "\x1B[58;2;9;8;7m");
+    assert_eq!(style::set_style(Style::Ul(Color::Reset)), "\x1B[59m");
+    let legacy = ColorEncoding::Legacy;
+    assert_eq!(style::set_style_as(Style::Fg(Color::Grey), legacy), "\x1B[37m");
+    assert_eq!(style::set_style_as(Style::Fg(Color::Red), legacy), "\x1B[91m");
+    assert_eq!(
+        style::set_style_as(Style::Bg(Color::AnsiValue(4)), legacy),
+        "\x1B[44m");
+    assert_eq!(
+        style::set_style_as(Style::Bg(Color::AnsiValue(42)), legacy),
+        "\x1B[48;5;42m");
+    assert_eq!(
+        style::set_style_as(Style::Fg(Color::Reset), legacy), "\x1B[39m");
+    let fx = Effect::Italic | Effect::Strikethrough | Effect::Overline
+        | Effect::CurlyUnderline;
+    assert_eq!(
+        style::set_style(Style::Fx(fx)), "\x1B[3m\x1B[9m\x1B[53m\x1B[4:3m");
+    assert_eq!(
+        style::begin_link("https://x.io/a;b", Some("p:1;")),
+        "\x1B]8;id=p1;https://x.io/a;b\x1B\\");
+    assert_eq!(style::begin_link("file:///tmp", None),
+        "\x1B]8;;file:///tmp\x1B\\");
+    assert_eq!(style::end_link(), "\x1B]8;;\x1B\\");
+    assert_eq!(
+        clipboard::set_clipboard(ClipboardTarget::Primary, "hi"),
+        "\x1B]52;p;aGk=\x1B\\");
+    assert_eq!(
+        clipboard::request_clipboard(ClipboardTarget::Clipboard),
+        "\x1B]52;c;?\x1B\\");
+    assert_eq!(
+        palette::set_color(
+            ColorSlot::Palette(1), Color::Rgb { r: 255, g: 0, b: 16 }),
+        "\x1B]4;1;rgb:ff/00/10\x1B\\");
+    assert_eq!(
+        palette::set_color(ColorSlot::Background, Color::Black),
+        "\x1B]11;rgb:00/00/00\x1B\\");
+    assert_eq!(
+        palette::set_color(ColorSlot::Foreground, Color::Reset),
+        "\x1B]110\x1B\\");
+    assert_eq!(
+        palette::reset_color(ColorSlot::Palette(12)), "\x1B]104;12\x1B\\");
+    assert_eq!(palette::reset_palette(), "\x1B]104\x1B\\");
+
+    // Into any fmt::Write...
+    let mut frame = String::new();
+    cursor::Goto(0, 0).write_ansi(&mut frame).unwrap();
+    style::SetStyle(Style::Bg(Color::AnsiValue(42)))
+        .write_ansi(&mut frame).unwrap();
+    assert_eq!(frame, "\x1B[1;1H\x1B[48;5;42m");
+
+    // ...or any io::Write.
+    let mut bytes: Vec<u8> = Vec::new();
+    screen::EnableAlt.write_io(&mut bytes).unwrap();
+    cursor::HideCursor.write_io(&mut bytes).unwrap();
+    assert_eq!(bytes, b"\x1B[?1049h\x1B[?25l");
+}
+
+#[test]
+fn test_output_buffer() {
+    // Counts how many writes reach the terminal.
+    struct Sink { bytes: Vec<u8>, writes: usize }
+    impl std::io::Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+
+    let mut buf = output::OutputBuffer::with_capacity(16);
+    buf.queue(&cursor::Goto(0, 0));
+    buf.push_str("hello");
+    assert_eq!(buf.len(), 11);
+    assert!(!buf.needs_flush());
+    buf.push_str(" world");
+    assert!(buf.needs_flush());
+
+    let mut sink = Sink { bytes: Vec::new(), writes: 0 };
+    buf.flush_into(&mut sink).unwrap();
+    assert_eq!(sink.writes, 1);
+    assert_eq!(sink.bytes, b"\x1B[1;1Hhello world");
+    assert!(buf.is_empty());
+
+    buf.set_auto_flush(usize::MAX);
+    buf.push_str(&"x".repeat(64));
+    assert!(!buf.needs_flush());
+}
+
+#[test]
+fn test_style_transition() {
+    let transition = |from, to| {
+        style::transition(from, to, ColorEncoding::Indexed)
+    };
+    let plain = TextStyle::default();
+    let bold_red = TextStyle {
+        fg: Color::Red, fx: Effect::Bold | Effect::Italic, ..plain };
+
+    assert_eq!(transition(plain, plain), "");
+    assert_eq!(transition(plain, bold_red), "\x1B[1;3;38;5;9m");
+    // Only italic goes; bold and the color stay.
+    let no_italic = TextStyle { fx: Effect::Bold as u32, ..bold_red };
+    assert_eq!(transition(bold_red, no_italic), "\x1B[23m");
+    // 22 turns dim off along with bold, so dim is put back.
+    let dim = TextStyle { fx: Effect::Bold | Effect::Dim, ..bold_red };
+    let only_dim = TextStyle { fx: Effect::Dim as u32, ..bold_red };
+    assert_eq!(transition(dim, only_dim), "\x1B[22;2m");
+    // Resetting is shorter than turning everything off one by one.
+    let busy = TextStyle {
+        fg: Color::Rgb { r: 1, g: 2, b: 3 }, bg: Color::Blue,
+        ul: Color::Red, fx: Effect::Bold | Effect::Reverse | Effect::Hide,
+    };
+    assert_eq!(transition(busy, plain), "\x1B[0m");
+    let underlined = TextStyle { fx: Effect::Underline as u32, ..plain };
+    assert_eq!(transition(busy, underlined), "\x1B[0;4m");
+
+    // `with` follows what the terminal does with each style.
+    let reset = plain.with(Style::Fg(Color::Red))
+        .with(Style::Fx(Effect::Reset | Effect::Underline));
+    assert!(reset == underlined);
+
+    // The basic colors in the classic codes.
+    let legacy = TextStyle {
+        fg: Color::DarkRed, bg: Color::White, ul: Color::Blue, ..plain };
+    assert_eq!(
+        style::transition(plain, legacy, ColorEncoding::Legacy),
+        "\x1B[31;107;58;5;12m");
+}
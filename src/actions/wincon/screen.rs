@@ -8,10 +8,12 @@
 // Write traits.
 
 use std::io::{Error, ErrorKind, Result};
+use std::mem::zeroed;
 use winapi::um::wincon::{
-    GetLargestConsoleWindowSize, COORD, SMALL_RECT,
+    GetLargestConsoleWindowSize, COORD, SMALL_RECT, CHAR_INFO,
     SetConsoleScreenBufferSize, SetConsoleWindowInfo,
     FillConsoleOutputCharacterA, FillConsoleOutputAttribute,
+    ScrollConsoleScreenBufferA,
 };
 use super::handle::{Handle, ConsoleInfo};
 use crate::common::enums::Clear;
@@ -75,6 +77,135 @@ pub fn clear(clr: Clear, conout: &Handle) -> Result<()> {
     Ok(())
 }
 
+// Scrolls the rows of `region` (from the top to the bottom row of the window,
+// inclusive) up by n lines, blanking the lines that come in at the bottom.
+// Without a region the whole window scrolls.
+pub fn scroll_up(
+    n: i16, region: Option<(i16, i16)>, conout: &Handle
+) -> Result<()> {
+    let info = ConsoleInfo::of(&conout)?;
+    let rect = region_rect(region, &info);
+    scroll(rect, COORD {X: 0, Y: rect.Top.saturating_sub(n)}, &info, conout)
+}
+
+// Scrolls the rows of `region` down by n lines (see `scroll_up`).
+pub fn scroll_down(
+    n: i16, region: Option<(i16, i16)>, conout: &Handle
+) -> Result<()> {
+    let info = ConsoleInfo::of(&conout)?;
+    let rect = region_rect(region, &info);
+    scroll(rect, COORD {X: 0, Y: rect.Top.saturating_add(n)}, &info, conout)
+}
+
+// Moves the cursor up a line, or scrolls `region` down by one if the cursor
+// is on its top row.
+pub fn reverse_index(
+    region: Option<(i16, i16)>, conout: &Handle
+) -> Result<()> {
+    let info = ConsoleInfo::of(&conout)?;
+    let rect = region_rect(region, &info);
+    let (_, row) = info.cursor_pos();
+    if row == rect.Top {
+        let dest = COORD {X: 0, Y: rect.Top.saturating_add(1)};
+        return scroll(rect, dest, &info, conout)
+    }
+    super::cursor::move_up(1, conout)
+}
+
+// The rows of a region of the window as a rect of the buffer.
+fn region_rect(region: Option<(i16, i16)>, info: &ConsoleInfo) -> SMALL_RECT {
+    let (w, _) = info.buffer_size();
+    let (_, _, bottom, top) = info.window_pos();
+    let (from, to) = match region {
+        Some((from, to)) => (
+            top.saturating_add(from), top.saturating_add(to).min(bottom)),
+        None => (top, bottom),
+    };
+    SMALL_RECT {Left: 0, Top: from, Right: w - 1, Bottom: to}
+}
+
+// Inserts n blank lines at the cursor row, pushing the lines below it down
+// to the bottom of the window.
+pub fn insert_lines(n: i16, conout: &Handle) -> Result<()> {
+    let info = ConsoleInfo::of(&conout)?;
+    let (_, row) = info.cursor_pos();
+    let (w, _) = info.buffer_size();
+    let (_, _, bottom, _) = info.window_pos();
+    let rect = SMALL_RECT {Left: 0, Top: row, Right: w - 1, Bottom: bottom};
+    scroll(rect, COORD {X: 0, Y: row.saturating_add(n)}, &info, conout)
+}
+
+// Deletes n lines from the cursor row down, pulling the lines below up and
+// leaving blank lines at the bottom of the window.
+pub fn delete_lines(n: i16, conout: &Handle) -> Result<()> {
+    let info = ConsoleInfo::of(&conout)?;
+    let (_, row) = info.cursor_pos();
+    let (w, _) = info.buffer_size();
+    let (_, _, bottom, _) = info.window_pos();
+    let rect = SMALL_RECT {Left: 0, Top: row, Right: w - 1, Bottom: bottom};
+    scroll(rect, COORD {X: 0, Y: row.saturating_sub(n)}, &info, conout)
+}
+
+// Inserts n blank cells at the cursor, pushing the rest of the line right.
+pub fn insert_chars(n: i16, conout: &Handle) -> Result<()> {
+    let info = ConsoleInfo::of(&conout)?;
+    let (col, row) = info.cursor_pos();
+    let (w, _) = info.buffer_size();
+    let rect = SMALL_RECT {Left: col, Top: row, Right: w - 1, Bottom: row};
+    scroll(rect, COORD {X: col.saturating_add(n), Y: row}, &info, conout)
+}
+
+// Deletes n cells at the cursor, pulling the rest of the line left.
+pub fn delete_chars(n: i16, conout: &Handle) -> Result<()> {
+    let info = ConsoleInfo::of(&conout)?;
+    let (col, row) = info.cursor_pos();
+    let (w, _) = info.buffer_size();
+    let rect = SMALL_RECT {Left: col, Top: row, Right: w - 1, Bottom: row};
+    scroll(rect, COORD {X: col.saturating_sub(n), Y: row}, &info, conout)
+}
+
+// Blanks n cells from the cursor on, up to the end of the line.
+pub fn erase_chars(n: i16, conout: &Handle) -> Result<()> {
+    let info = ConsoleInfo::of(&conout)?;
+    let (col, row) = info.cursor_pos();
+    let (w, _) = info.buffer_size();
+    let n = n.min(w - col).max(0) as u32;
+    let at = COORD {X: col, Y: row};
+    // Same attributes as `clear` uses for blank cells.
+    let ch = ' ' as i8;
+    let fx = info.attributes() & !0xdf00;
+    let mut len = 0;
+
+    unsafe {
+        if FillConsoleOutputCharacterA(conout.0, ch, n, at, &mut len) == 0 {
+            return Err(Error::last_os_error())
+        }
+
+        if FillConsoleOutputAttribute(conout.0, fx, n, at, &mut len) == 0 {
+            return Err(Error::last_os_error())
+        }
+    }
+    Ok(())
+}
+
+// Moves the cells of `rect` so that its top left corner lands on `dest`.
+// Nothing outside of `rect` changes: cells moved past its edges are lost,
+// and the cells left behind are blanked.
+fn scroll(
+    rect: SMALL_RECT, dest: COORD, info: &ConsoleInfo, conout: &Handle
+) -> Result<()> {
+    unsafe {
+        let mut fill: CHAR_INFO = zeroed();
+        *fill.Char.AsciiChar_mut() = ' ' as i8;
+        fill.Attributes = info.attributes() & !0xdf00;
+        if ScrollConsoleScreenBufferA(
+            conout.0, &rect, &rect, dest, &fill) == 0 {
+            return Err(Error::last_os_error())
+        }
+    }
+    Ok(())
+}
+
 pub fn size(conout: &Handle) -> Result<(i16, i16)> {
     let info = ConsoleInfo::of(&conout)?;
     let size = info.terminal_size();
@@ -39,6 +39,8 @@ pub fn set_style(
         Style::Fx(f) => {
             effects(f, current)
         }
+        // The console draws underlines in the text color.
+        Style::Ul(_) => current,
     };
     unsafe {
         if SetConsoleTextAttribute(conout.0, updated) == 0 {
@@ -0,0 +1,57 @@
+// Standard base64 (RFC 4648, with padding) for the terminal sequences that
+// carry binary data, such as OSC 52.
+
+use std::fmt::{ Result, Write };
+
+
+const ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Encodes `bytes` straight into `out`. Encoding consecutive slices whose
+// lengths are multiples of 3 gives the same text as encoding them at once.
+pub fn encode_into(bytes: &[u8], out: &mut dyn Write) -> Result {
+    for group in bytes.chunks(3) {
+        let b = [
+            group[0],
+            group.get(1).copied().unwrap_or(0),
+            group.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= group.len() {
+                let index = (n >> (18 - 6 * i)) & 0x3F;
+                out.write_char(ALPHABET[index as usize] as char)?;
+            } else {
+                out.write_char('=')?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    // Writing into a `String` cannot fail.
+    let _ = encode_into(bytes, &mut text);
+    text
+}
+
+// Returns `None` if `text` is not valid base64. Padding is optional.
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        let value = ALPHABET.iter().position(|a| *a == c)? as u32;
+        acc = acc << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    // A single leftover character cannot encode a whole byte.
+    if bits >= 6 { return None }
+    Some(bytes)
+}
@@ -0,0 +1,421 @@
+// Conversions between colors. Distances are measured with CIE94 in CIELAB,
+// where equal distances look about equally different, against the default
+// xterm palette.
+
+use std::env;
+use std::sync::OnceLock;
+use super::enums::{ Color, ColorDepth, xterm_rgb };
+use super::terminfo;
+
+
+// Works out how many colors the terminal supports from the environment and
+// the terminal's terminfo entry.
+pub fn detect_depth() -> ColorDepth {
+    depth_from(
+        |name| env::var(name).ok(),
+        |term| terminfo::number(term, terminfo::COLORS))
+}
+
+// `var` looks up an environment variable; `colors` looks up the terminfo
+// `colors` capability of a terminal.
+pub(crate) fn depth_from<V, C>(var: V, colors: C) -> ColorDepth
+where V: Fn(&str) -> Option<String>, C: Fn(&str) -> Option<i32> {
+    let detected = || {
+        if let Some(value) = var("COLORTERM") {
+            if value == "truecolor" || value == "24bit" {
+                return ColorDepth::TrueColor;
+            }
+        }
+        let term = match var("TERM") {
+            Some(term) if !term.is_empty() => term,
+            _ => return ColorDepth::Mono,
+        };
+        if term == "dumb" { return ColorDepth::Mono }
+        if term.ends_with("-direct") { return ColorDepth::TrueColor }
+        if term.ends_with("-256color") { return ColorDepth::Ansi256 }
+        match colors(&term) {
+            Some(n) if n >= 1 << 24 => ColorDepth::TrueColor,
+            Some(n) if n >= 256 => ColorDepth::Ansi256,
+            Some(n) if n >= 16 => ColorDepth::Ansi16,
+            Some(n) if n >= 8 => ColorDepth::Ansi8,
+            Some(_) => ColorDepth::Mono,
+            // An unknown terminal is still very likely to do the basics.
+            None => ColorDepth::Ansi16,
+        }
+    };
+
+    // FORCE_COLOR=0..3 as Node.js and others read it; any other value just
+    // forces color on.
+    if let Some(force) = var("FORCE_COLOR") {
+        return match force.as_str() {
+            "0" | "false" => ColorDepth::Mono,
+            "2" => ColorDepth::Ansi256,
+            "3" => ColorDepth::TrueColor,
+            _ => detected().max(ColorDepth::Ansi16),
+        };
+    }
+    if matches!(var("NO_COLOR"), Some(value) if !value.is_empty()) {
+        return ColorDepth::Mono;
+    }
+    if matches!(var("CLICOLOR_FORCE"), Some(value) if value != "0") {
+        return detected().max(ColorDepth::Ansi16);
+    }
+    detected()
+}
+
+
+impl Color {
+    /// The nearest color that a terminal with `depth` can show. Colors it
+    /// can already show are returned as they are; `Mono` leaves only
+    /// `Color::Reset`.
+    pub fn downsample(self, depth: ColorDepth) -> Color {
+        let rgb = match self.to_rgb() {
+            Some(rgb) => rgb,
+            None => return self,
+        };
+        match depth {
+            ColorDepth::TrueColor => self,
+            ColorDepth::Ansi256 => match self {
+                Color::Rgb{..} => Color::AnsiValue(nearest(rgb, 16..=255)),
+                _ => self,
+            },
+            ColorDepth::Ansi16 => match self.ansi_value() {
+                Some(n) if n < 16 => named(n),
+                _ => named(nearest(rgb, 0..=15)),
+            },
+            ColorDepth::Ansi8 => match self.ansi_value() {
+                Some(n) if n < 8 => named(n),
+                _ => named(nearest(rgb, 0..=7)),
+            },
+            ColorDepth::Mono => Color::Reset,
+        }
+    }
+}
+
+// The named color for the first 16 palette entries.
+fn named(n: u8) -> Color {
+    match n {
+        0 => Color::Black,
+        1 => Color::DarkRed,
+        2 => Color::DarkGreen,
+        3 => Color::DarkYellow,
+        4 => Color::DarkBlue,
+        5 => Color::DarkMagenta,
+        6 => Color::DarkCyan,
+        7 => Color::Grey,
+        8 => Color::DarkGrey,
+        9 => Color::Red,
+        10 => Color::Green,
+        11 => Color::Yellow,
+        12 => Color::Blue,
+        13 => Color::Magenta,
+        14 => Color::Cyan,
+        15 => Color::White,
+        _ => Color::AnsiValue(n),
+    }
+}
+
+// The palette entry in `range` that looks the most like `rgb`.
+fn nearest(rgb: (u8, u8, u8), range: std::ops::RangeInclusive<u8>) -> u8 {
+    static PALETTE: OnceLock<Vec<[f64; 3]>> = OnceLock::new();
+    let palette = PALETTE.get_or_init(|| {
+        (0..=255).map(|n| to_lab(xterm_rgb(n))).collect()
+    });
+    let lab = to_lab(rgb);
+    let distance = |n: &u8| delta_e94(&lab, &palette[*n as usize]);
+    range.min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(0)
+}
+
+// The squared CIE94 color difference (graphic arts weights) of `other` from
+// the reference color `lab`. Unlike plain distance in CIELAB, differences in
+// saturation count for less than differences in lightness and hue.
+pub(crate) fn delta_e94(lab: &[f64; 3], other: &[f64; 3]) -> f64 {
+    let c1 = lab[1].hypot(lab[2]);
+    let c2 = other[1].hypot(other[2]);
+    let dl = lab[0] - other[0];
+    let dc = c1 - c2;
+    let (da, db) = (lab[1] - other[1], lab[2] - other[2]);
+    let dh2 = (da * da + db * db - dc * dc).max(0.0);
+    let sc = 1.0 + 0.045 * c1;
+    let sh = 1.0 + 0.015 * c1;
+    dl * dl + (dc / sc).powi(2) + dh2 / (sh * sh)
+}
+
+// sRGB to CIELAB (D65 white).
+pub(crate) fn to_lab((r, g, b): (u8, u8, u8)) -> [f64; 3] {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        match c <= 0.04045 {
+            true => c / 12.92,
+            false => ((c + 0.055) / 1.055).powf(2.4),
+        }
+    };
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f64| {
+        match t > 216.0 / 24389.0 {
+            true => t.cbrt(),
+            false => (24389.0 / 27.0 * t + 16.0) / 116.0,
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+
+impl Color {
+    /// Parses `#rgb`, `#rrggbb`, `rgb(r, g, b)`, `hsl(h, s%, l%)` or an
+    /// X11/CSS color name (case and spaces do not matter).
+    pub fn parse(text: &str) -> Option<Color> {
+        let text = text.trim();
+        if let Some(hex) = text.strip_prefix('#') {
+            return parse_hex(hex);
+        }
+        let lower = text.to_ascii_lowercase();
+        if let Some(args) = function_args(&lower, "rgb") {
+            let mut channels = [0u8; 3];
+            for (channel, arg) in channels.iter_mut().zip(&args) {
+                *channel = match arg.strip_suffix('%') {
+                    Some(pct) => percent(pct)? * 255.0,
+                    None => arg.parse::<f64>().ok()?.clamp(0.0, 255.0),
+                }.round() as u8;
+            }
+            let [r, g, b] = channels;
+            return Some(Color::Rgb { r, g, b });
+        }
+        if let Some(args) = function_args(&lower, "hsl") {
+            let h = args[0].trim_end_matches("deg").parse::<f64>().ok()?;
+            let s = percent(args[1].strip_suffix('%')?)?;
+            let l = percent(args[2].strip_suffix('%')?)?;
+            return Some(Color::from_hsl(h, s, l));
+        }
+        let name: String = lower.chars().filter(|c| *c != ' ').collect();
+        let name = name.replace("grey", "gray");
+        NAMES.iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, rgb)| Color::Rgb {
+                r: (rgb >> 16) as u8, g: (rgb >> 8) as u8, b: *rgb as u8 })
+    }
+
+    /// Hue in degrees (0 to 360), saturation and lightness (0 to 1).
+    /// `None` for `Reset`.
+    pub fn to_hsl(self) -> Option<(f64, f64, f64)> {
+        let (r, g, b) = self.to_rgb()?;
+        let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+        if d == 0.0 { return Some((0.0, 0.0, l)) }
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            ((g - b) / d).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / d + 2.0
+        } else {
+            (r - g) / d + 4.0
+        };
+        Some((h * 60.0, s, l))
+    }
+
+    /// The color with hue `h` in degrees, and saturation `s` and lightness
+    /// `l` from 0 to 1.
+    pub fn from_hsl(h: f64, s: f64, l: f64) -> Color {
+        let (s, l) = (s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+        let h = h.rem_euclid(360.0) / 60.0;
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u8 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = l - c / 2.0;
+        let channel = |v: f64| ((v + m) * 255.0).round() as u8;
+        Color::Rgb { r: channel(r), g: channel(g), b: channel(b) }
+    }
+
+    /// The nearest entry of the 256 color palette. `None` for `Reset`.
+    pub fn to_ansi256(self) -> Option<u8> {
+        self.downsample(ColorDepth::Ansi256).ansi_value()
+    }
+
+    /// Mixes in `other` by `amount` (0 keeps `self`, 1 gives `other`).
+    /// Mixing with `Reset` leaves the color as it is.
+    pub fn blend(self, other: Color, amount: f64) -> Color {
+        let (a, b) = match (self.to_rgb(), other.to_rgb()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return self,
+        };
+        let t = amount.clamp(0.0, 1.0);
+        let mix = |x: u8, y: u8| {
+            (x as f64 + (y as f64 - x as f64) * t).round() as u8
+        };
+        Color::Rgb { r: mix(a.0, b.0), g: mix(a.1, b.1), b: mix(a.2, b.2) }
+    }
+
+    /// Raises the HSL lightness by `amount` (0 to 1).
+    pub fn lighten(self, amount: f64) -> Color {
+        match self.to_hsl() {
+            Some((h, s, l)) => Color::from_hsl(h, s, l + amount),
+            None => self,
+        }
+    }
+
+    /// Lowers the HSL lightness by `amount` (0 to 1).
+    pub fn darken(self, amount: f64) -> Color {
+        self.lighten(-amount)
+    }
+
+    /// The relative luminance as WCAG 2 defines it, from 0 (black) to 1
+    /// (white). `None` for `Reset`.
+    pub fn luminance(self) -> Option<f64> {
+        let (r, g, b) = self.to_rgb()?;
+        let linear = |c: u8| {
+            let c = c as f64 / 255.0;
+            match c <= 0.03928 {
+                true => c / 12.92,
+                false => ((c + 0.055) / 1.055).powf(2.4),
+            }
+        };
+        Some(0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b))
+    }
+
+    /// The WCAG 2 contrast ratio between the two colors, from 1 to 21. Body
+    /// text wants at least 4.5. `None` if either is `Reset`.
+    pub fn contrast(self, other: Color) -> Option<f64> {
+        let (a, b) = (self.luminance()?, other.luminance()?);
+        Some((a.max(b) + 0.05) / (a.min(b) + 0.05))
+    }
+
+    /// Of `candidates`, the one that is easiest to read on `self` as a
+    /// background. `None` if there is none to compare.
+    pub fn most_readable(self, candidates: &[Color]) -> Option<Color> {
+        candidates.iter()
+            .filter_map(|c| Some((*c, self.contrast(*c)?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(c, _)| c)
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = std::io::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Color::parse(text).ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Unknown color: {}", text)))
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.is_ascii() { return None }
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        3 => {
+            let mut c = hex.chars().map(|c| channel(&c.to_string()));
+            let (r, g, b) = (c.next()??, c.next()??, c.next()??);
+            Some(Color::Rgb { r: r * 17, g: g * 17, b: b * 17 })
+        }
+        6 => Some(Color::Rgb {
+            r: channel(&hex[0..2])?,
+            g: channel(&hex[2..4])?,
+            b: channel(&hex[4..6])?,
+        }),
+        _ => None,
+    }
+}
+
+// The three arguments of `name(a, b, c)`, split on commas or spaces.
+fn function_args<'a>(text: &'a str, name: &str) -> Option<Vec<&'a str>> {
+    let inner = text.strip_prefix(name)?.trim_start()
+        .strip_prefix('(')?.strip_suffix(')')?;
+    let args: Vec<&str> = inner
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .collect();
+    if args.len() == 3 { Some(args) } else { None }
+}
+
+// A percentage as a fraction from 0 to 1.
+fn percent(text: &str) -> Option<f64> {
+    Some((text.parse::<f64>().ok()? / 100.0).clamp(0.0, 1.0))
+}
+
+// The CSS color names, which X11 shares. Where the two disagree (gray,
+// green, maroon and purple), CSS wins. Spaces are left out and "grey" is
+// spelled "gray" before looking a name up.
+const NAMES: [(&str, u32); 142] = [
+    ("aliceblue", 0xF0F8FF), ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF), ("aquamarine", 0x7FFFD4), ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC), ("bisque", 0xFFE4C4), ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD), ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2), ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887), ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00), ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50), ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC), ("crimson", 0xDC143C), ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B), ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B), ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400), ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B), ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00), ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000), ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F), ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F), ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3), ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF), ("dimgray", 0x696969),
+    ("dodgerblue", 0x1E90FF), ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0), ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF), ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF), ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520), ("gray", 0x808080), ("green", 0x008000),
+    ("greenyellow", 0xADFF2F), ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4), ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082), ("ivory", 0xFFFFF0), ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA), ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00), ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6), ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF), ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3), ("lightgreen", 0x90EE90),
+    ("lightpink", 0xFFB6C1), ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA), ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899), ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0), ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32), ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF), ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA), ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3), ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371), ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A), ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585), ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA), ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5), ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080), ("navyblue", 0x000080), ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000), ("olivedrab", 0x6B8E23), ("orange", 0xFFA500),
+    ("orangered", 0xFF4500), ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA), ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE), ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5), ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F), ("pink", 0xFFC0CB), ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6), ("purple", 0x800080),
+    ("rebeccapurple", 0x663399), ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F), ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513), ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460), ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE), ("sienna", 0xA0522D), ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB), ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090), ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F), ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C), ("teal", 0x008080), ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347), ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE), ("wheat", 0xF5DEB3), ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5), ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
@@ -2,8 +2,16 @@
 // Contains:
 // * Clear
 // * Style
+// * TextStyle
 // * Color
+// * ColorDepth
+// * ColorEncoding
 // * Effect
+// * CursorShape
+// * ClipboardTarget
+// * ColorSlot
+// * ThemeSlot
+// * Query
 // * InputEvent
 // * MouseEvent
 // * MouseButton
@@ -33,6 +41,51 @@ pub enum Style {
     Fg(Color),
     Bg(Color),
     Fx(u32),
+    /// the color of underlines (`Color::Reset` follows the text color)
+    Ul(Color),
+}
+
+
+// Every attribute that SGR sets, as one value. `Color::Reset` and no
+// effects is the terminal's default.
+#[derive(Copy, Clone, PartialEq)]
+pub struct TextStyle {
+    pub fg: Color,
+    pub bg: Color,
+    pub ul: Color,
+    pub fx: u32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            fg: Color::Reset,
+            bg: Color::Reset,
+            ul: Color::Reset,
+            fx: 0,
+        }
+    }
+}
+
+impl TextStyle {
+    /// What the terminal ends up with after `style` is applied to `self`.
+    /// Colors replace the current ones; effects are added, except that
+    /// `Effect::Reset` first goes back to the default.
+    pub fn with(self, style: Style) -> Self {
+        match style {
+            Style::Fg(fg) => Self { fg, ..self },
+            Style::Bg(bg) => Self { bg, ..self },
+            Style::Ul(ul) => Self { ul, ..self },
+            Style::Fx(fx) => {
+                let reset = Effect::Reset as u32;
+                let base = match fx & reset {
+                    0 => self,
+                    _ => Self::default(),
+                };
+                Self { fx: base.fx | (fx & !reset), ..base }
+            }
+        }
+    }
 }
 
 
@@ -59,6 +112,91 @@ pub enum Color {
     AnsiValue(u8),
 }
 
+impl Color {
+    /// The index of the color in the 256 color palette. `None` for `Reset`
+    /// and `Rgb` colors.
+    pub fn ansi_value(self) -> Option<u8> {
+        match self {
+            Color::Black => Some(0),
+            Color::DarkRed => Some(1),
+            Color::DarkGreen => Some(2),
+            Color::DarkYellow => Some(3),
+            Color::DarkBlue => Some(4),
+            Color::DarkMagenta => Some(5),
+            Color::DarkCyan => Some(6),
+            Color::Grey => Some(7),
+            Color::DarkGrey => Some(8),
+            Color::Red => Some(9),
+            Color::Green => Some(10),
+            Color::Yellow => Some(11),
+            Color::Blue => Some(12),
+            Color::Magenta => Some(13),
+            Color::Cyan => Some(14),
+            Color::White => Some(15),
+            Color::AnsiValue(n) => Some(n),
+            Color::Rgb{..} | Color::Reset => None,
+        }
+    }
+
+    /// The RGB value of the color, taking palette colors to be xterm's
+    /// defaults. `None` for `Reset`.
+    pub fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        match self {
+            Color::Rgb{r, g, b} => Some((r, g, b)),
+            Color::Reset => None,
+            _ => self.ansi_value().map(xterm_rgb),
+        }
+    }
+}
+
+// How many colors a terminal can show, from fewest to most.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ColorDepth {
+    /// no colors at all
+    Mono,
+    /// the 8 basic colors
+    Ansi8,
+    /// the 8 basic colors and their bright variants
+    Ansi16,
+    /// the xterm 256 color palette
+    Ansi256,
+    /// any 24-bit RGB color
+    TrueColor,
+}
+
+// How SGR sequences name the 16 basic colors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColorEncoding {
+    /// as palette entries, eg. `38;5;1` (needs 256 color support)
+    #[default]
+    Indexed,
+    /// with the classic codes `30–37`, `40–47`, `90–97` and `100–107`,
+    /// which every color terminal understands
+    Legacy,
+}
+
+// The default RGB value of an entry of xterm's 256 color palette.
+pub fn xterm_rgb(n: u8) -> (u8, u8, u8) {
+    const SYSTEM: [(u8, u8, u8); 16] = [
+        (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+        (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+        (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+        (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+    ];
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match n {
+        0..=15 => SYSTEM[n as usize],
+        16..=231 => {
+            let i = (n - 16) as usize;
+            (LEVELS[i / 36], LEVELS[i / 6 % 6], LEVELS[i % 6])
+        }
+        _ => {
+            let v = 8 + 10 * (n - 232);
+            (v, v, v)
+        }
+    }
+}
+
 #[cfg(windows)]
 pub const RESET: u16 = 0xFFFF;
 #[cfg(windows)]
@@ -98,15 +236,15 @@ pub fn foreground(color: Color, current: u16, reset: u16) -> u16 {
         Color::White => FG_RED | FG_GREEN | FG_BLUE,
         Color::Grey => FG_RED | FG_GREEN | FG_BLUE | FG_INTENSE,
         Color::Reset => RESET,
-        Color::Rgb{r: _, g: _, b: _} => IGNORE,
-        Color::AnsiValue(_) => IGNORE,
+        Color::Rgb{r: _, g: _, b: _} | Color::AnsiValue(_) => {
+            // The console only has the 16 named colors.
+            let named = color.downsample(ColorDepth::Ansi16);
+            return foreground(named, current, reset);
+        }
     };
     if attrib == RESET {
         attrib = reset & 0x000f;
     }
-    if attrib == IGNORE {
-        attrib = current & 0x000f;
-    }
     // (imdaveho) NOTE: We need to isolate Colors in Windows
     // because Color attributes mix. So if you previously had
     // Color::Red and you wanted Color::Blue, that would end up
@@ -135,15 +273,14 @@ pub fn background(color: Color, current: u16, reset: u16) -> u16 {
         Color::White => BG_RED | BG_GREEN | BG_BLUE,
         Color::Grey => BG_RED | BG_GREEN | BG_BLUE | BG_INTENSE,
         Color::Reset => RESET,
-        Color::Rgb{r: _, g: _, b: _} => IGNORE,
-        Color::AnsiValue(_) => IGNORE,
+        Color::Rgb{r: _, g: _, b: _} | Color::AnsiValue(_) => {
+            let named = color.downsample(ColorDepth::Ansi16);
+            return background(named, current, reset);
+        }
     };
     if attrib == RESET {
         attrib = reset & 0x00f0;
     }
-    if attrib == IGNORE {
-        attrib = current & 0x00f0;
-    }
     // (imdaveho) NOTE: We need to isolate Colors in Windows
     // because Color attributes mix. So if you previously had
     // Color::Red and you wanted Color::Blue, that would end up
@@ -156,20 +293,17 @@ pub fn background(color: Color, current: u16, reset: u16) -> u16 {
 #[cfg(windows)]
 pub fn effects(fx: u32, current: u16) -> u16 {
     let mut attrib = current;
-    let available_effects = [
-        Effect::Reset, 
-        Effect::Bold, 
-        Effect::Dim, 
-        Effect::Underline, 
-        Effect::Reverse, 
-        Effect::Hide
-    ];
-    for effect in &available_effects {
+    for effect in &Effect::ALL {
         if (fx & *effect as u32) != 0 {
             match *effect {
                 Effect::Bold => attrib |= FG_INTENSE,
                 Effect::Dim => attrib &= !FG_INTENSE,
-                Effect::Underline => attrib |= UNDERLINE,
+                // The console has a single underline style.
+                Effect::Underline
+                | Effect::DoubleUnderline
+                | Effect::CurlyUnderline
+                | Effect::DottedUnderline
+                | Effect::DashedUnderline => attrib |= UNDERLINE,
                 Effect::Reverse => attrib |= REVERSE,
                 Effect::Hide => {
                     // FOREGROUND and BACKGROUND color differ by 4
@@ -186,6 +320,14 @@ pub fn effects(fx: u32, current: u16) -> u16 {
                     attrib = updated_fg | current & !0x000f
                 },
                 Effect::Reset => attrib = current & !0xdf00,
+                // Nothing close enough to fall back on; the text is shown
+                // without these. (The console's top grid line would do for
+                // an overline, but it only draws under DBCS code pages.)
+                Effect::Italic
+                | Effect::SlowBlink
+                | Effect::RapidBlink
+                | Effect::Strikethrough
+                | Effect::Overline => (),
             }
         }
     }
@@ -198,9 +340,51 @@ pub enum Effect {
     Reset = 1 << (0 + 9),
     Bold = 1 << (1 + 9),
     Dim = 1 << (2 + 9),
+    Italic = 1 << (3 + 9),
     Underline = 1 << (4 + 9),
+    SlowBlink = 1 << (5 + 9),
+    RapidBlink = 1 << (6 + 9),
     Reverse = 1 << (7 + 9),
     Hide = 1 << (8 + 9),
+    Strikethrough = 1 << (9 + 9),
+    // The rest have SGR codes that do not fit the `n + 9` layout.
+    Overline = 1 << 19,
+    DoubleUnderline = 1 << 20,
+    CurlyUnderline = 1 << 21,
+    DottedUnderline = 1 << 22,
+    DashedUnderline = 1 << 23,
+}
+
+impl Effect {
+    /// Every effect, in the order they are applied.
+    pub const ALL: [Effect; 15] = [
+        Effect::Reset, Effect::Bold, Effect::Dim, Effect::Italic,
+        Effect::Underline, Effect::SlowBlink, Effect::RapidBlink,
+        Effect::Reverse, Effect::Hide, Effect::Strikethrough,
+        Effect::Overline, Effect::DoubleUnderline, Effect::CurlyUnderline,
+        Effect::DottedUnderline, Effect::DashedUnderline,
+    ];
+
+    /// The SGR parameter that turns the effect on.
+    pub fn sgr(self) -> &'static str {
+        match self {
+            Effect::Reset => "0",
+            Effect::Bold => "1",
+            Effect::Dim => "2",
+            Effect::Italic => "3",
+            Effect::Underline => "4",
+            Effect::SlowBlink => "5",
+            Effect::RapidBlink => "6",
+            Effect::Reverse => "7",
+            Effect::Hide => "8",
+            Effect::Strikethrough => "9",
+            Effect::Overline => "53",
+            Effect::DoubleUnderline => "4:2",
+            Effect::CurlyUnderline => "4:3",
+            Effect::DottedUnderline => "4:4",
+            Effect::DashedUnderline => "4:5",
+        }
+    }
 }
 
 impl BitOr<u32> for Effect {
@@ -251,16 +435,155 @@ impl BitAnd<Effect> for u32 {
     }
 }
 
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CursorShape {
+    /// whatever the terminal (or its user) has configured
+    Default,
+    BlinkingBlock,
+    SteadyBlock,
+    BlinkingUnderline,
+    SteadyUnderline,
+    BlinkingBar,
+    SteadyBar,
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ClipboardTarget {
+    /// the clipboard that copy and paste use
+    Clipboard,
+    /// the primary selection (X11); middle click pastes it
+    Primary,
+}
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorSlot {
+    /// the default text color (OSC 10)
+    Foreground,
+    /// the default background color (OSC 11)
+    Background,
+    /// the text cursor color (OSC 12)
+    Cursor,
+    /// an entry of the 256 color palette (OSC 4)
+    Palette(u8),
+}
+
+
+// What a color is used for, for themes to fill in (see
+// `crate::common::theme`).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ThemeSlot {
+    Text,
+    Background,
+    Primary,
+    Secondary,
+    Accent,
+    Muted,
+    Border,
+    Selection,
+    Success,
+    Info,
+    Warning,
+    Error,
+}
+
+impl ThemeSlot {
+    pub const ALL: [ThemeSlot; 12] = [
+        ThemeSlot::Text, ThemeSlot::Background, ThemeSlot::Primary,
+        ThemeSlot::Secondary, ThemeSlot::Accent, ThemeSlot::Muted,
+        ThemeSlot::Border, ThemeSlot::Selection, ThemeSlot::Success,
+        ThemeSlot::Info, ThemeSlot::Warning, ThemeSlot::Error,
+    ];
+
+    /// The name the slot goes by in theme files.
+    pub fn name(self) -> &'static str {
+        match self {
+            ThemeSlot::Text => "text",
+            ThemeSlot::Background => "background",
+            ThemeSlot::Primary => "primary",
+            ThemeSlot::Secondary => "secondary",
+            ThemeSlot::Accent => "accent",
+            ThemeSlot::Muted => "muted",
+            ThemeSlot::Border => "border",
+            ThemeSlot::Selection => "selection",
+            ThemeSlot::Success => "success",
+            ThemeSlot::Info => "info",
+            ThemeSlot::Warning => "warning",
+            ThemeSlot::Error => "error",
+        }
+    }
+
+    /// The basic color that stands in for the slot when a theme leaves it
+    /// out.
+    pub fn default_color(self) -> Color {
+        match self {
+            ThemeSlot::Text | ThemeSlot::Background => Color::Reset,
+            ThemeSlot::Primary => Color::Blue,
+            ThemeSlot::Secondary => Color::Magenta,
+            ThemeSlot::Accent | ThemeSlot::Info => Color::Cyan,
+            ThemeSlot::Muted => Color::DarkGrey,
+            ThemeSlot::Border => Color::Grey,
+            ThemeSlot::Selection => Color::DarkBlue,
+            ThemeSlot::Success => Color::Green,
+            ThemeSlot::Warning => Color::Yellow,
+            ThemeSlot::Error => Color::Red,
+        }
+    }
+}
+
+
+// Requests for information that the terminal answers with an InputEvent.
+#[derive(Clone, PartialEq)]
+pub enum Query {
+    /// cursor position report (CPR); answered by `InputEvent::CursorPos`
+    CursorPos,
+    /// primary device attributes (DA1); answered by `DeviceAttrs`
+    DeviceAttrs,
+    /// state of a DEC private mode (DECRQM); answered by `ModeReport`
+    Mode(u16),
+    /// current value of a color (OSC 4/10/11/12); answered by `ColorReport`
+    Color(ColorSlot),
+    /// a terminfo capability (XTGETTCAP); answered by `TermCap`
+    TermCap(String),
+}
+
+
 // (imdaveho) NOTE: Clone for moving parsed events over channels.
-// See: crate::terminal::dispatch::input_handle
+// See: crate::terminal::dispatch::Dispatcher
 #[derive(Clone)]
 pub enum InputEvent {
     Keyboard(KeyEvent),
     Mouse(MouseEvent),
     CursorPos(i16, i16),
+    DeviceAttrs(Vec<u16>),
+    // (mode, state) where state is 0: not recognized, 1: set, 2: reset,
+    // 3: permanently set, 4: permanently reset.
+    ModeReport(u16, u8),
+    ColorReport(ColorSlot, Color),
+    // (name, value) where value is `None` if the capability is unknown.
+    TermCap(String, Option<String>),
+    // The decoded contents of a clipboard (OSC 52). Arrives whenever the
+    // terminal (or its user) allows it, so it is not a `Query` reply.
+    Clipboard(ClipboardTarget, String),
     Unsupported,
 }
 
+impl InputEvent {
+    // Whether this event answers `query`.
+    pub fn answers(&self, query: &Query) -> bool {
+        match (self, query) {
+            (Self::CursorPos(_, _), Query::CursorPos) => true,
+            (Self::DeviceAttrs(_), Query::DeviceAttrs) => true,
+            (Self::ModeReport(m, _), Query::Mode(n)) => m == n,
+            (Self::ColorReport(s, _), Query::Color(t)) => s == t,
+            (Self::TermCap(a, _), Query::TermCap(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 
 #[derive(Copy, Clone)]
 pub enum MouseEvent {
@@ -1,6 +1,10 @@
 // Common types, enums, and traits that is used across the library.
 
+pub mod base64;
+pub mod color;
 pub mod enums;
+pub mod terminfo;
+pub mod theme;
 pub mod unicode;
 
 #[cfg(test)]
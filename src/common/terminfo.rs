@@ -0,0 +1,93 @@
+// Reads numeric capabilities from the compiled terminfo database, without
+// linking to ncurses. Both the legacy format and the extended number format
+// of ncurses 6.1 are understood.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+
+// Index of `colors` among the numeric capabilities.
+pub const COLORS: usize = 13;
+
+const MAGIC_LEGACY: u16 = 0o432;
+const MAGIC_32BIT: u16 = 0o1036;
+
+
+// The value of the numeric capability at `index` for the terminal `term`.
+// `None` if there is no entry for the terminal or it lacks the capability.
+pub fn number(term: &str, index: usize) -> Option<i32> {
+    let data = find(term)?;
+    parse_number(&data, index)
+}
+
+// Looks for the entry of `term` where ncurses would: $TERMINFO, ~/.terminfo,
+// $TERMINFO_DIRS, then the usual system directories.
+fn find(term: &str) -> Option<Vec<u8>> {
+    let first = term.chars().next()?;
+    if term.contains('/') { return None }
+
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") { dirs.push(dir.into()) }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Ok(list) = env::var("TERMINFO_DIRS") {
+        for dir in list.split(':') {
+            match dir {
+                "" => dirs.push("/usr/share/terminfo".into()),
+                _ => dirs.push(dir.into()),
+            }
+        }
+    }
+    for dir in &[
+        "/etc/terminfo", "/lib/terminfo",
+        "/usr/share/terminfo", "/usr/lib/terminfo",
+    ] {
+        dirs.push(dir.into());
+    }
+
+    // Entries are filed under their first letter, or under its hex code on
+    // case-insensitive file systems (eg. macOS).
+    let subdirs = [first.to_string(), format!("{:x}", first as u32)];
+    dirs.iter()
+        .flat_map(|dir| subdirs.iter().map(move |sub| dir.join(sub)))
+        .find_map(|dir| fs::read(dir.join(term)).ok())
+}
+
+// Reads a numeric capability out of a compiled entry.
+pub(crate) fn parse_number(data: &[u8], index: usize) -> Option<i32> {
+    let short = |at: usize| -> Option<i16> {
+        let bytes = data.get(at..at.checked_add(2)?)?;
+        Some(i16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    // Section sizes are never negative, except in a corrupt entry.
+    let size = |at: usize| -> Option<usize> {
+        match short(at)? {
+            n if n < 0 => None,
+            n => Some(n as usize),
+        }
+    };
+    let (magic, names, bools, nums) =
+        (short(0)? as u16, size(2)?, size(4)?, size(6)?);
+    let width: usize = match magic {
+        MAGIC_LEGACY => 2,
+        MAGIC_32BIT => 4,
+        _ => return None,
+    };
+    if index >= nums { return None }
+
+    // The numbers start on an even byte after the header, names and bools.
+    let mut at = names.checked_add(bools)?.checked_add(12)?;
+    if at % 2 != 0 { at += 1 }
+    at = at.checked_add(index.checked_mul(width)?)?;
+    let value = match width {
+        2 => short(at)? as i32,
+        _ => {
+            let bytes = data.get(at..at.checked_add(4)?)?;
+            i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        }
+    };
+    // Negative values mark absent or cancelled capabilities.
+    if value < 0 { None } else { Some(value) }
+}
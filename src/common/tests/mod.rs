@@ -74,3 +74,202 @@ fn test_effect_bitwise_traits() {
     let check_some = combo & Effect::Bold;
     assert_ne!(check_some, 0);
 }
+
+#[test]
+fn test_color_palette() {
+    use super::enums::{ Color, xterm_rgb };
+    assert_eq!(xterm_rgb(1), (205, 0, 0));
+    assert_eq!(xterm_rgb(16), (0, 0, 0));
+    assert_eq!(xterm_rgb(196), (255, 0, 0));
+    assert_eq!(xterm_rgb(110), (135, 175, 215));
+    assert_eq!(xterm_rgb(244), (128, 128, 128));
+    assert_eq!(Color::Cyan.to_rgb(), Some((0, 255, 255)));
+    assert_eq!(Color::Reset.to_rgb(), None);
+}
+
+#[test]
+fn test_base64() {
+    use super::base64::{ encode, decode };
+    assert_eq!(encode(b""), "");
+    assert_eq!(encode(b"f"), "Zg==");
+    assert_eq!(encode(b"fo"), "Zm8=");
+    assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    assert_eq!(decode("Zm9vYg==").unwrap(), b"foob");
+    assert_eq!(decode("Zm9vYg").unwrap(), b"foob");
+    assert!(decode("Z").is_none());
+    assert!(decode("Zm9*").is_none());
+
+    // Chunks on 3 byte boundaries join up.
+    let data: Vec<u8> = (0..=255).collect();
+    let joined: String = data.chunks(9).map(encode).collect();
+    assert_eq!(joined, encode(&data));
+    assert_eq!(decode(&joined).unwrap(), data);
+}
+
+#[test]
+fn test_color_downsample() {
+    use super::enums::{ Color, ColorDepth::* };
+    let orange = Color::Rgb { r: 255, g: 135, b: 0 };
+    assert!(orange.downsample(TrueColor) == orange);
+    assert!(orange.downsample(Ansi256) == Color::AnsiValue(208));
+    assert!(orange.downsample(Ansi16) == Color::Red);
+    assert!(orange.downsample(Ansi8) == Color::DarkYellow);
+    assert!(orange.downsample(Mono) == Color::Reset);
+
+    let navy = Color::Rgb { r: 0, g: 0, b: 128 };
+    assert!(navy.downsample(Ansi16) == Color::DarkBlue);
+    assert!(Color::AnsiValue(9).downsample(Ansi16) == Color::Red);
+    assert!(Color::AnsiValue(250).downsample(Ansi16) == Color::Grey);
+    assert!(Color::White.downsample(Ansi8) == Color::Grey);
+    assert!(Color::Red.downsample(Ansi256) == Color::Red);
+    assert!(Color::Reset.downsample(Ansi8) == Color::Reset);
+}
+
+#[test]
+fn test_color_parse_and_convert() {
+    use super::enums::Color;
+    let rgb = |r, g, b| Color::Rgb { r, g, b };
+    assert!(Color::parse("#ff8700") == Some(rgb(255, 135, 0)));
+    assert!(Color::parse("#F80") == Some(rgb(255, 136, 0)));
+    assert!(Color::parse("rgb(255, 135, 0)") == Some(rgb(255, 135, 0)));
+    assert!(Color::parse("RGB(100%, 0%, 0%)") == Some(rgb(255, 0, 0)));
+    assert!(Color::parse("hsl(120, 100%, 25%)") == Some(rgb(0, 128, 0)));
+    assert!(Color::parse("Dark Slate Grey") == Some(rgb(47, 79, 79)));
+    assert!("rebeccapurple".parse::<Color>().unwrap() == rgb(102, 51, 153));
+    assert!(Color::parse("#ff87").is_none());
+    assert!(Color::parse("rgb(1, 2)").is_none());
+    assert!("nope".parse::<Color>().is_err());
+
+    let (h, s, l) = rgb(255, 135, 0).to_hsl().unwrap();
+    assert_eq!((h.round(), (s * 100.0).round(), (l * 100.0).round()),
+        (32.0, 100.0, 50.0));
+    assert!(Color::from_hsl(h, s, l) == rgb(255, 135, 0));
+    assert_eq!(rgb(255, 135, 0).to_ansi256(), Some(208));
+    assert_eq!(Color::Reset.to_ansi256(), None);
+
+    let (black, white) = (rgb(0, 0, 0), rgb(255, 255, 255));
+    assert!(black.blend(white, 0.5) == rgb(128, 128, 128));
+    assert!(black.blend(Color::Reset, 0.5) == black);
+    assert!(rgb(128, 0, 0).lighten(0.25) == rgb(255, 0, 0));
+    assert!(white.darken(1.0) == black);
+
+    assert_eq!(black.contrast(white).map(|c| c.round()), Some(21.0));
+    assert_eq!(white.contrast(white), Some(1.0));
+    let navy = rgb(0, 0, 128);
+    assert!(navy.most_readable(&[black, white]) == Some(white));
+    assert!(Color::Yellow.most_readable(&[black, white]) == Some(black));
+}
+
+#[test]
+fn test_detect_depth() {
+    use std::collections::HashMap;
+    use super::color::depth_from;
+    use super::enums::ColorDepth::{ self, * };
+
+    let detect = |vars: &[(&str, &str)], colors: Option<i32>| -> ColorDepth {
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        depth_from(|name| vars.get(name).cloned(), |_| colors)
+    };
+
+    assert_eq!(detect(&[], None), Mono);
+    assert_eq!(detect(&[("TERM", "dumb")], Some(8)), Mono);
+    assert_eq!(detect(&[("TERM", "xterm")], Some(8)), Ansi8);
+    assert_eq!(detect(&[("TERM", "linux")], Some(16)), Ansi16);
+    assert_eq!(detect(&[("TERM", "weird")], None), Ansi16);
+    assert_eq!(detect(&[("TERM", "xterm-256color")], Some(8)), Ansi256);
+    assert_eq!(detect(&[("TERM", "xterm-direct")], None), TrueColor);
+    assert_eq!(detect(&[("TERM", "kitty")], Some(1 << 24)), TrueColor);
+    assert_eq!(
+        detect(&[("TERM", "xterm"), ("COLORTERM", "truecolor")], Some(8)),
+        TrueColor);
+
+    assert_eq!(detect(&[("TERM", "xterm"), ("NO_COLOR", "1")], Some(8)), Mono);
+    assert_eq!(detect(&[("TERM", "xterm"), ("NO_COLOR", "")], Some(8)), Ansi8);
+    assert_eq!(
+        detect(&[("NO_COLOR", "1"), ("FORCE_COLOR", "3")], None), TrueColor);
+    assert_eq!(detect(&[("TERM", "xterm"), ("FORCE_COLOR", "0")], None), Mono);
+    assert_eq!(detect(&[("FORCE_COLOR", "1")], None), Ansi16);
+    assert_eq!(detect(&[("CLICOLOR_FORCE", "1")], None), Ansi16);
+    assert_eq!(detect(&[("CLICOLOR_FORCE", "0")], None), Mono);
+}
+
+#[test]
+fn test_terminfo_numbers() {
+    use super::terminfo::{ parse_number, COLORS };
+
+    // A header, 3 bytes of names, 2 bools (so the numbers need a pad
+    // byte), and 14 numbers of which only `colors` is set.
+    let entry = |magic: u16, width: usize, colors: i32| -> Vec<u8> {
+        let mut data = Vec::new();
+        for n in &[magic, 3, 2, 14, 0, 0] {
+            data.extend_from_slice(&n.to_le_bytes());
+        }
+        data.extend_from_slice(b"xt\0");
+        data.extend_from_slice(&[1, 0]);
+        data.push(0);
+        for i in 0..14 {
+            let value = if i == COLORS { colors } else { -1 };
+            data.extend_from_slice(&value.to_le_bytes()[..width]);
+        }
+        data
+    };
+    assert_eq!(parse_number(&entry(0o432, 2, 256), COLORS), Some(256));
+    assert_eq!(
+        parse_number(&entry(0o1036, 4, 1 << 24), COLORS), Some(1 << 24));
+    assert_eq!(parse_number(&entry(0o432, 2, 8), 0), None);
+    assert_eq!(parse_number(&entry(0o432, 2, 8), 20), None);
+    assert_eq!(parse_number(b"\x1A\x01", COLORS), None);
+
+    // A corrupt entry with negative section sizes.
+    let mut corrupt = entry(0o432, 2, 8);
+    corrupt[2..4].copy_from_slice(&(-1i16).to_le_bytes());
+    assert_eq!(parse_number(&corrupt, COLORS), None);
+    let mut corrupt = entry(0o432, 2, 8);
+    corrupt[6..8].copy_from_slice(&(-2i16).to_le_bytes());
+    assert_eq!(parse_number(&corrupt, usize::MAX), None);
+}
+
+#[test]
+fn test_theme() {
+    use super::enums::{ Color, ColorDepth::*, ThemeSlot };
+    use super::theme::Theme;
+    let rgb = |r, g, b| Color::Rgb { r, g, b };
+
+    let theme = Theme::parse("
+        # comments and blank lines are skipped
+
+        name = test
+        Dark Red = #dc322f
+        error = #dc322f, 160, red
+        primary = rgb(38, 139, 210), dark_blue
+        muted = dark-gray
+    ").unwrap();
+    assert_eq!(theme.name(), "test");
+    assert!(theme.color(ThemeSlot::Error, TrueColor) == rgb(220, 50, 47));
+    assert!(theme.color(ThemeSlot::Error, Ansi256) == Color::AnsiValue(160));
+    assert!(theme.color(ThemeSlot::Error, Ansi16) == Color::Red);
+    assert!(theme.color(ThemeSlot::Error, Ansi8) == Color::DarkRed);
+    assert!(theme.color(ThemeSlot::Error, Mono) == Color::Reset);
+    assert!(theme.color(ThemeSlot::Primary, Ansi256) == Color::AnsiValue(32));
+    assert!(theme.color(ThemeSlot::Primary, Ansi8) == Color::DarkBlue);
+    assert!(theme.color(ThemeSlot::Muted, TrueColor) == Color::DarkGrey);
+    // Left out: the slot's default color.
+    assert!(theme.color(ThemeSlot::Warning, Ansi16) == Color::Yellow);
+
+    // Basic colors become the theme's where the terminal can show them.
+    assert!(theme.resolve(Color::DarkRed, TrueColor) == rgb(220, 50, 47));
+    assert!(theme.resolve(Color::DarkRed, Ansi16) == Color::DarkRed);
+    assert!(theme.resolve(Color::Green, TrueColor) == Color::Green);
+
+    let err = Theme::parse("error = red\nnope = red").err().unwrap();
+    assert_eq!(err.to_string(), "line 2: unknown key `nope`");
+    assert!(Theme::parse("red = #f00, blue").is_err());
+    assert!(Theme::parse("error = #ff").is_err());
+
+    let solarized = Theme::builtin("Solarized").unwrap();
+    assert_eq!(solarized.name(), "solarized");
+    assert!(solarized.color(ThemeSlot::Background, Ansi16) == Color::Reset);
+    assert!(Theme::builtin("gruvbox").is_some());
+    assert!(Theme::builtin("nope").is_none());
+}
@@ -0,0 +1,298 @@
+// Themes map what a color is for (`ThemeSlot`) and the 16 basic `Color`s to
+// concrete colors, so that the look of an app can change without touching
+// its code.
+//
+// A theme file has one `key = value` per line; blank lines and lines that
+// start with `#` are left out:
+//
+//     name = solarized
+//     dark_red = #dc322f
+//     error = #dc322f, 160, red
+//
+// The keys are `name`, the slot names (see `ThemeSlot::name`) and the names
+// of the basic colors (`black`, `dark_red`, ..., `grey`, `dark_grey`, ...,
+// `white`). A slot takes a list of colors, best first: the first one that
+// the terminal can show is used. A value is a basic color name, a palette
+// index, `default` for the terminal's own color, or anything that
+// `Color::parse` takes. Case, spaces, `-` and `_` do not matter in names.
+
+use std::fs;
+use std::io::{ Error, ErrorKind, Result };
+use std::path::Path;
+use super::enums::{ Color, ColorDepth, ThemeSlot };
+
+
+const SOLARIZED: &str = "
+name = solarized
+black = #073642
+dark_red = #dc322f
+dark_green = #859900
+dark_yellow = #b58900
+dark_blue = #268bd2
+dark_magenta = #d33682
+dark_cyan = #2aa198
+grey = #eee8d5
+dark_grey = #002b36
+red = #cb4b16
+green = #586e75
+yellow = #657b83
+blue = #839496
+magenta = #6c71c4
+cyan = #93a1a1
+white = #fdf6e3
+text = #839496, default
+background = #002b36, default
+primary = #268bd2, blue
+secondary = #6c71c4, magenta
+accent = #cb4b16, yellow
+muted = #586e75, dark_grey
+border = #073642, dark_grey
+selection = #073642, dark_blue
+success = #859900, green
+info = #2aa198, cyan
+warning = #b58900, yellow
+error = #dc322f, red
+";
+
+const GRUVBOX: &str = "
+name = gruvbox
+black = #282828
+dark_red = #cc241d
+dark_green = #98971a
+dark_yellow = #d79921
+dark_blue = #458588
+dark_magenta = #b16286
+dark_cyan = #689d6a
+grey = #a89984
+dark_grey = #928374
+red = #fb4934
+green = #b8bb26
+yellow = #fabd2f
+blue = #83a598
+magenta = #d3869b
+cyan = #8ec07c
+white = #ebdbb2
+text = #ebdbb2, default
+background = #282828, default
+primary = #83a598, blue
+secondary = #d3869b, magenta
+accent = #fe8019, yellow
+muted = #928374, dark_grey
+border = #504945, dark_grey
+selection = #504945, dark_blue
+success = #b8bb26, green
+info = #8ec07c, cyan
+warning = #fabd2f, yellow
+error = #fb4934, red
+";
+
+
+#[derive(Clone, Default)]
+pub struct Theme {
+    name: String,
+    // The choices for each slot, best first, in the order of
+    // `ThemeSlot::ALL`. Empty means the slot's default color.
+    slots: [Vec<Color>; 12],
+    // What each basic color stands for, by palette index.
+    named: [Option<Color>; 16],
+}
+
+impl Theme {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), ..Self::default() }
+    }
+
+    /// One of the themes that come with the library (`solarized` or
+    /// `gruvbox`).
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match normalize(name).as_str() {
+            "solarized" => Theme::parse(SOLARIZED).ok(),
+            "gruvbox" => Theme::parse(GRUVBOX).ok(),
+            _ => None,
+        }
+    }
+
+    /// Reads a theme file. A theme without a `name` is named after the
+    /// file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Theme> {
+        let path = path.as_ref();
+        let mut theme = Theme::parse(&fs::read_to_string(path)?)?;
+        if theme.name.is_empty() {
+            if let Some(stem) = path.file_stem() {
+                theme.name = stem.to_string_lossy().into_owned();
+            }
+        }
+        Ok(theme)
+    }
+
+    /// Reads a theme from the text of a theme file.
+    pub fn parse(text: &str) -> Result<Theme> {
+        let mut theme = Theme::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+            let invalid = |what: &str| Error::new(
+                ErrorKind::InvalidData, format!("line {}: {}", i + 1, what));
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| invalid("expected `key = value`"))?;
+            let (key, value) = (normalize(key), value.trim());
+            if key == "name" {
+                theme.name = value.to_string();
+                continue;
+            }
+            let mut colors = Vec::new();
+            for item in split_list(value) {
+                colors.push(parse_value(item).ok_or_else(|| invalid(
+                    &format!("unknown color `{}`", item)))?);
+            }
+            if let Some(slot) = ThemeSlot::ALL.iter()
+                .find(|s| s.name() == key) {
+                theme.set(*slot, &colors);
+            } else if let Some(basic) = basic_color(&key) {
+                match colors.as_slice() {
+                    [color] => theme.set_named(basic, *color),
+                    _ => return Err(invalid("expected a single color")),
+                }
+            } else {
+                return Err(invalid(&format!("unknown key `{}`", key)));
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the colors of a slot, best first.
+    pub fn set(&mut self, slot: ThemeSlot, colors: &[Color]) {
+        self.slots[slot as usize] = colors.to_vec();
+    }
+
+    /// Sets what one of the 16 basic colors stands for. Other colors are
+    /// left as they are.
+    pub fn set_named(&mut self, basic: Color, color: Color) {
+        if let Some(n) = basic_index(basic) {
+            self.named[n as usize] = Some(color);
+        }
+    }
+
+    /// What the theme makes of a basic color, if it says.
+    pub fn named(&self, basic: Color) -> Option<Color> {
+        basic_index(basic).and_then(|n| self.named[n as usize])
+    }
+
+    /// The color of `slot` for a terminal with `depth`: the first choice
+    /// that the terminal can show, or else the best one brought down to
+    /// `depth`.
+    pub fn color(&self, slot: ThemeSlot, depth: ColorDepth) -> Color {
+        let default = [slot.default_color()];
+        let colors = match self.slots[slot as usize].as_slice() {
+            [] => &default[..],
+            colors => colors,
+        };
+        colors.iter()
+            .find_map(|c| self.pick(*c, depth))
+            .unwrap_or_else(|| self.resolve(colors[0], depth))
+    }
+
+    /// `color` as the theme has it, for a terminal with `depth`. Basic
+    /// colors become what the theme made of them, where the terminal can
+    /// show that.
+    pub fn resolve(&self, color: Color, depth: ColorDepth) -> Color {
+        self.pick(color, depth).unwrap_or_else(|| color.downsample(depth))
+    }
+
+    fn pick(&self, color: Color, depth: ColorDepth) -> Option<Color> {
+        let color = match self.named(color) {
+            Some(named) if fits(named, depth) => named,
+            _ => color,
+        };
+        match fits(color, depth) {
+            true => Some(color.downsample(depth)),
+            false => None,
+        }
+    }
+}
+
+// Whether a terminal with `depth` can show `color` well enough. RGB colors
+// pass with 256 colors, where the nearest palette entry stands in.
+fn fits(color: Color, depth: ColorDepth) -> bool {
+    let needs = match color {
+        Color::Reset => ColorDepth::Mono,
+        Color::Rgb { .. } => ColorDepth::Ansi256,
+        Color::AnsiValue(n) if n >= 16 => ColorDepth::Ansi256,
+        _ => match color.ansi_value() {
+            Some(n) if n < 8 => ColorDepth::Ansi8,
+            _ => ColorDepth::Ansi16,
+        },
+    };
+    depth >= needs
+}
+
+// The palette index of one of the 16 basic `Color` variants.
+fn basic_index(color: Color) -> Option<u8> {
+    match color {
+        Color::AnsiValue(_) | Color::Rgb { .. } | Color::Reset => None,
+        _ => color.ansi_value(),
+    }
+}
+
+const BASIC_NAMES: [(&str, Color); 16] = [
+    ("black", Color::Black), ("darkred", Color::DarkRed),
+    ("darkgreen", Color::DarkGreen), ("darkyellow", Color::DarkYellow),
+    ("darkblue", Color::DarkBlue), ("darkmagenta", Color::DarkMagenta),
+    ("darkcyan", Color::DarkCyan), ("grey", Color::Grey),
+    ("darkgrey", Color::DarkGrey), ("red", Color::Red),
+    ("green", Color::Green), ("yellow", Color::Yellow),
+    ("blue", Color::Blue), ("magenta", Color::Magenta),
+    ("cyan", Color::Cyan), ("white", Color::White),
+];
+
+fn basic_color(name: &str) -> Option<Color> {
+    BASIC_NAMES.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+}
+
+// Basic color names come before the CSS names, so `red` is the terminal's
+// red rather than #ff0000.
+fn parse_value(text: &str) -> Option<Color> {
+    let name = normalize(text);
+    if name == "default" || name == "reset" {
+        return Some(Color::Reset);
+    }
+    if let Some(color) = basic_color(&name) {
+        return Some(color);
+    }
+    if let Ok(n) = text.parse::<u8>() {
+        return Some(Color::AnsiValue(n));
+    }
+    Color::parse(text)
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .collect::<String>()
+        .to_ascii_lowercase()
+        .replace("gray", "grey")
+}
+
+// Splits on the commas that are not inside parentheses, as in
+// `rgb(1, 2, 3), red`.
+fn split_list(value: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, ch) in value.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    items.push(value[start..].trim());
+    items
+}
@@ -5,3 +5,6 @@ pub mod unix;
 
 #[cfg(windows)]
 pub mod windows;
+
+#[cfg(all(test, unix))]
+mod tests;
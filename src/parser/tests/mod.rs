@@ -0,0 +1,191 @@
+use std::time::Duration;
+use crate::common::enums::{ InputEvent, KeyEvent };
+use super::unix::Parser;
+
+
+fn is_key(event: &InputEvent, key: KeyEvent) -> bool {
+    match event {
+        InputEvent::Keyboard(k) => k.enumerate() == key.enumerate()
+            && k.values() == key.values(),
+        _ => false,
+    }
+}
+
+#[test]
+fn test_parser_split_sequences() {
+    let mut parser = Parser::new();
+    assert!(parser.feed(b"\x1B[").is_empty());
+    assert!(parser.has_pending());
+    let events = parser.feed(b"Aa");
+    assert_eq!(events.len(), 2);
+    assert!(is_key(&events[0], KeyEvent::Up));
+    assert!(is_key(&events[1], KeyEvent::Char('a')));
+    assert!(!parser.has_pending());
+    assert!(parser.timeout().is_none());
+
+    let bytes = "褐".as_bytes();
+    assert!(parser.feed(&bytes[..1]).is_empty());
+    let events = parser.feed(&bytes[1..]);
+    assert!(is_key(&events[0], KeyEvent::Char('褐')));
+
+    let events = parser.feed(b"\x1B[12;40R");
+    match events[0] {
+        InputEvent::CursorPos(39, 11) => (),
+        _ => panic!("expected a cursor position report"),
+    }
+}
+
+#[test]
+fn test_parser_esc_timeout() {
+    let mut parser = Parser::new();
+    assert!(parser.feed(b"\x1B").is_empty());
+    assert!(parser.timeout().is_some());
+    assert!(parser.flush_timeout().is_empty());
+    let events = parser.flush();
+    assert!(is_key(&events[0], KeyEvent::Esc));
+
+    let mut parser = Parser::with_esc_timeout(Duration::from_secs(0));
+    assert!(parser.feed(b"\x1B").is_empty());
+    assert_eq!(parser.timeout(), Some(Duration::from_secs(0)));
+    let events = parser.flush_timeout();
+    assert!(is_key(&events[0], KeyEvent::Esc));
+}
+
+#[test]
+fn test_parser_malformed_input() {
+    let mut parser = Parser::new();
+    let events = parser.feed(b"\x1B[1;2;3R\x1B[99999999~");
+    assert_eq!(events.len(), 2);
+    for event in &events {
+        match event {
+            InputEvent::Unsupported => (),
+            _ => panic!("expected an unsupported event"),
+        }
+    }
+}
+
+#[test]
+fn test_reader_keeps_backlog() {
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::unix::io::FromRawFd;
+    use super::unix::Reader;
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let (tty, mut writer) = unsafe {
+        (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    let mut reader = Reader::from_file(tty);
+    let is_pos = |e: &InputEvent| matches!(e, InputEvent::CursorPos(_, _));
+
+    writer.write_all(b"j\x1B[5;3Rk").unwrap();
+    match reader.read_until(Duration::from_millis(500), is_pos).unwrap() {
+        Some(InputEvent::CursorPos(2, 4)) => (),
+        _ => panic!("expected a cursor position report"),
+    }
+    let backlog = reader.read_events(Duration::from_secs(0)).unwrap();
+    assert_eq!(backlog.len(), 2);
+    assert!(is_key(&backlog[0], KeyEvent::Char('j')));
+    assert!(is_key(&backlog[1], KeyEvent::Char('k')));
+
+    writer.write_all(b"x").unwrap();
+    let reply = reader.read_until(Duration::from_millis(50), is_pos).unwrap();
+    assert!(reply.is_none());
+    let backlog = reader.read_events(Duration::from_secs(0)).unwrap();
+    assert!(is_key(&backlog[0], KeyEvent::Char('x')));
+}
+
+#[test]
+fn test_parser_query_replies() {
+    use crate::common::enums::{ Color, ColorSlot };
+    let mut parser = Parser::new();
+    let events = parser.feed(
+        b"\x1B[?62;22c\x1B[?2026;2$y\x1B]11;rgb:ffff/8080/0000\x1B\\\
+          \x1B]4;1;rgb:cd/00/00\x07\x1BP1+r636F6C6F7273=323536\x1B\\\
+          \x1BP0+r666F6F\x1B\\");
+    assert_eq!(events.len(), 6);
+    match &events[0] {
+        InputEvent::DeviceAttrs(attrs) => assert_eq!(attrs, &[62, 22]),
+        _ => panic!("expected device attributes"),
+    }
+    match events[1] {
+        InputEvent::ModeReport(2026, 2) => (),
+        _ => panic!("expected a mode report"),
+    }
+    match events[2] {
+        InputEvent::ColorReport(ColorSlot::Background, c) => {
+            assert!(c == Color::Rgb { r: 255, g: 128, b: 0 })
+        }
+        _ => panic!("expected the background color"),
+    }
+    match events[3] {
+        InputEvent::ColorReport(ColorSlot::Palette(1), c) => {
+            assert!(c == Color::Rgb { r: 205, g: 0, b: 0 })
+        }
+        _ => panic!("expected a palette color"),
+    }
+    match &events[4] {
+        InputEvent::TermCap(name, Some(value)) => {
+            assert_eq!((name.as_str(), value.as_str()), ("colors", "256"))
+        }
+        _ => panic!("expected a capability"),
+    }
+    match &events[5] {
+        InputEvent::TermCap(name, None) => assert_eq!(name, "foo"),
+        _ => panic!("expected an unknown capability"),
+    }
+
+    // A lone `ESC ]` or `ESC P` is still Alt + key once it times out.
+    assert!(parser.feed(b"\x1B]").is_empty());
+    assert!(is_key(&parser.flush()[0], KeyEvent::Alt(']')));
+}
+
+#[test]
+fn test_parser_alt_bracket_and_p() {
+    // Typing on after Alt + ']' or Alt + 'P' is not taken for a string.
+    let mut parser = Parser::new();
+    let events = parser.feed(b"\x1B]abc\x1BPx");
+    let keys = [
+        KeyEvent::Alt(']'), KeyEvent::Char('a'), KeyEvent::Char('b'),
+        KeyEvent::Char('c'), KeyEvent::Alt('P'), KeyEvent::Char('x'),
+    ];
+    assert_eq!(events.len(), keys.len());
+    for (event, key) in events.iter().zip(keys.iter()) {
+        assert!(is_key(event, *key));
+    }
+
+    // A reply is still read as one when it comes in pieces.
+    assert!(parser.feed(b"\x1B]1").is_empty());
+    let events = parser.feed(b"1;rgb:0/0/0\x07\x1BP0");
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0], InputEvent::ColorReport(_, _)));
+    let events = parser.feed(b"+r666F6F\x1B\\");
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0], InputEvent::TermCap(_, None)));
+}
+
+#[test]
+fn test_parse_clipboard_reply() {
+    use crate::common::enums::ClipboardTarget;
+    let mut parser = Parser::new();
+    // "héllo" split across two reads, then an invalid payload.
+    assert!(parser.feed(b"\x1B]52;c;aMOp").is_empty());
+    let events = parser.feed(b"bGxv\x07\x1B]52;p;!!\x1B\\");
+    assert_eq!(events.len(), 2);
+    match &events[0] {
+        InputEvent::Clipboard(ClipboardTarget::Clipboard, text) => {
+            assert_eq!(text, "héllo")
+        }
+        _ => panic!("expected the clipboard contents"),
+    }
+    assert!(matches!(events[1], InputEvent::Unsupported));
+}
+
+#[test]
+fn test_poll_millis_rounds_up() {
+    use super::unix::poll_millis;
+    assert_eq!(poll_millis(Duration::from_secs(0)), 0);
+    assert_eq!(poll_millis(Duration::from_micros(300)), 1);
+    assert_eq!(poll_millis(Duration::from_micros(2001)), 3);
+    assert_eq!(poll_millis(Duration::from_secs(u64::MAX)), libc::c_int::MAX);
+}
@@ -1,10 +1,340 @@
 // Unix specific functions that parse ANSI escape sequences from the stdin
 // bytestream and map to the proper input event.
 
+use std::collections::VecDeque;
+use std::fs::{ File, OpenOptions };
+use std::io::{ Result, Error, ErrorKind };
+use std::os::unix::{ fs::OpenOptionsExt, io::{ AsRawFd, RawFd } };
+use std::time::{ Duration, Instant };
+use libc::{ c_int, c_void, poll, pollfd, read, O_NONBLOCK, POLLIN };
 use crate::common::enums::{
     InputEvent::{*, self}, KeyEvent::*,
-    MouseEvent::*, MouseButton
+    MouseEvent::*, MouseButton, Color, ColorSlot, ClipboardTarget
 };
+use crate::common::base64;
+
+
+// How long to wait for the rest of an escape sequence before treating the
+// bytes received so far as complete (eg. a lone press of the ESC key).
+pub const ESC_TIMEOUT: Duration = Duration::from_millis(50);
+
+
+// Opens the controlling terminal for non-blocking reads. The raw fd of the
+// returned file can be registered with an external poll/epoll loop, and the
+// bytes read from it handed to a `Parser`.
+pub fn open_tty() -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(O_NONBLOCK)
+        .open("/dev/tty")
+}
+
+
+// A stateful parser that turns chunks of bytes read from the tty into input
+// events. Escape sequences split across reads are held back until the rest
+// arrives; if nothing more arrives within the ESC timeout, the held bytes
+// are parsed as they are.
+pub struct Parser {
+    buffer: Vec<u8>,
+    since: Option<Instant>,
+    esc_timeout: Duration,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::with_esc_timeout(ESC_TIMEOUT)
+    }
+
+    pub fn with_esc_timeout(esc_timeout: Duration) -> Self {
+        Self { buffer: Vec::new(), since: None, esc_timeout }
+    }
+
+    // Here are bytes, give me events.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<InputEvent> {
+        self.buffer.extend_from_slice(bytes);
+        self.drain(false)
+    }
+
+    // Whether part of an escape sequence is waiting for more bytes.
+    pub fn has_pending(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    // How long until `flush_timeout` will produce the held back events. Use
+    // this as the timeout of the external poll. `None` if nothing is held.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.since.map(|since| {
+            self.esc_timeout
+                .checked_sub(since.elapsed())
+                .unwrap_or_default()
+        })
+    }
+
+    // Returns the held back events once the ESC timeout has passed.
+    pub fn flush_timeout(&mut self) -> Vec<InputEvent> {
+        match self.timeout() {
+            Some(t) if t == Duration::from_secs(0) => self.flush(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Parses the held back bytes as they are, regardless of the timeout.
+    pub fn flush(&mut self) -> Vec<InputEvent> {
+        self.drain(true)
+    }
+
+    // Reads whatever is available on a non-blocking `fd` and parses it,
+    // including any events whose ESC timeout has passed.
+    pub fn read_available(&mut self, fd: RawFd) -> Result<Vec<InputEvent>> {
+        let mut events = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            match read_fd(fd, &mut buf) {
+                Ok(0) => {
+                    events.extend(self.flush());
+                    if events.is_empty() {
+                        return Err(Error::new(
+                            ErrorKind::UnexpectedEof,
+                            "The tty input was closed."));
+                    }
+                    return Ok(events);
+                }
+                Ok(n) => events.extend(self.feed(&buf[..n])),
+                Err(e) => match e.kind() {
+                    ErrorKind::Interrupted => (),
+                    ErrorKind::WouldBlock => break,
+                    _ => return Err(e),
+                },
+            }
+        }
+        events.extend(self.flush_timeout());
+        Ok(events)
+    }
+
+    // Waits up to `timeout` for `fd` to become readable, then reads and
+    // parses it. An empty `Vec` means that nothing arrived in time.
+    pub fn read_events(
+        &mut self, fd: RawFd, timeout: Duration
+    ) -> Result<Vec<InputEvent>> {
+        let timeout = match self.timeout() {
+            Some(t) if t < timeout => t,
+            _ => timeout,
+        };
+        match wait_readable(fd, timeout)? {
+            false => Ok(self.flush_timeout()),
+            true => {
+                let mut buf = [0u8; 1024];
+                match read_fd(fd, &mut buf) {
+                    Ok(0) => Err(Error::new(
+                        ErrorKind::UnexpectedEof, "The tty input was closed.")),
+                    Ok(n) => Ok(self.feed(&buf[..n])),
+                    Err(e) => match e.kind() {
+                        ErrorKind::Interrupted
+                        | ErrorKind::WouldBlock => Ok(Vec::new()),
+                        _ => Err(e),
+                    },
+                }
+            }
+        }
+    }
+
+    fn drain(&mut self, force: bool) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        let mut start = 0;
+        while start < self.buffer.len() {
+            // Alt + ']' and Alt + 'P' start like OSC and DCS strings. Only
+            // what looks like a reply is read as one, or whatever is typed
+            // next would be swallowed up to the next BEL or ST.
+            if let [b'\x1B', intro @ (b']' | b'P'), tail @ ..] =
+                &self.buffer[start..] {
+                match is_reply(*intro, tail) {
+                    Some(true) => (),
+                    None if !force => break,
+                    _ => {
+                        events.push(Keyboard(Alt(*intro as char)));
+                        start += 2;
+                        continue
+                    }
+                }
+            }
+            let mut iter = Bytes {
+                bytes: &self.buffer[start..], pos: 0, is_exhausted: false };
+            let event = match iter.next() {
+                Some(item) => parse_event(item, &mut iter),
+                None => break,
+            };
+            // The sequence ran past the end of the buffer: wait for more.
+            if iter.is_exhausted && !force { break }
+            start += iter.pos;
+            events.push(event);
+        }
+        self.buffer.drain(..start);
+        self.since = match self.buffer.is_empty() {
+            true => None,
+            false => Some(self.since.unwrap_or_else(Instant::now)),
+        };
+        events
+    }
+}
+
+// Iterates over a byte slice while remembering how far it got, and whether
+// the parser asked for more bytes than there were.
+struct Bytes<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    is_exhausted: bool,
+}
+
+impl Iterator for Bytes<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        match self.bytes.get(self.pos) {
+            Some(b) => { self.pos += 1; Some(*b) }
+            None => { self.is_exhausted = true; None }
+        }
+    }
+}
+
+// `timeout` in whole milliseconds for `poll`, rounded up: a held back ESC
+// with less than a millisecond left would otherwise poll with 0 and spin.
+pub(crate) fn poll_millis(timeout: Duration) -> c_int {
+    let mut millis = timeout.as_millis();
+    if timeout.subsec_nanos() % 1_000_000 != 0 { millis += 1 }
+    millis.min(c_int::MAX as u128) as c_int
+}
+
+// Waits up to `timeout` for `fd` to become readable (or hung up). `false`
+// if it did not in time, or the wait was interrupted.
+pub(crate) fn wait_readable(fd: RawFd, timeout: Duration) -> Result<bool> {
+    let mut pfd = pollfd { fd, events: POLLIN, revents: 0 };
+    match unsafe { poll(&mut pfd, 1, poll_millis(timeout)) } {
+        -1 => {
+            let err = Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted { return Err(err) }
+            Ok(false)
+        }
+        0 => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+fn read_fd(fd: RawFd, buf: &mut [u8]) -> Result<usize> {
+    let n = unsafe { read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+    if n < 0 { return Err(Error::last_os_error()) }
+    Ok(n as usize)
+}
+
+// Reads events from the tty on behalf of callers that wait for something in
+// particular (eg. the reply to a query). Events that arrive in the meantime
+// are kept in a backlog and handed out by the next `read_events` instead of
+// being lost.
+pub struct Reader {
+    tty: Box<dyn AsRawFd + Send>,
+    parser: Parser,
+    backlog: VecDeque<InputEvent>,
+}
+
+impl Reader {
+    pub fn new() -> Result<Self> {
+        Ok(Self::from_file(open_tty()?))
+    }
+
+    pub fn from_file(tty: File) -> Self {
+        Self::from_source(tty)
+    }
+
+    // Reads from anything backed by a file descriptor that `poll` accepts,
+    // eg. a tty opened by the caller or one end of a pty.
+    pub fn from_source<T>(tty: T) -> Self
+    where T: AsRawFd + Send + 'static {
+        Self {
+            tty: Box::new(tty),
+            parser: Parser::new(),
+            backlog: VecDeque::new(),
+        }
+    }
+
+    // Returns the backlog if there is one; otherwise waits up to `timeout`
+    // for new events.
+    pub fn read_events(
+        &mut self, timeout: Duration
+    ) -> Result<Vec<InputEvent>> {
+        if !self.backlog.is_empty() {
+            return Ok(self.take_backlog());
+        }
+        self.parser.read_events(self.tty.as_raw_fd(), timeout)
+    }
+
+    // How long until `read_events` has something to return without new
+    // input: zero if there is a backlog, the ESC timeout if part of a
+    // sequence is held back, `None` otherwise.
+    pub fn timeout(&self) -> Option<Duration> {
+        match self.backlog.is_empty() {
+            true => self.parser.timeout(),
+            false => Some(Duration::from_secs(0)),
+        }
+    }
+
+    // Waits up to `timeout` for a newly read event that `matches`. Every
+    // other event read in the meantime is added to the backlog. Returns
+    // `None` if nothing matched in time.
+    pub fn read_until<F>(
+        &mut self, timeout: Duration, mut matches: F
+    ) -> Result<Option<InputEvent>>
+    where F: FnMut(&InputEvent) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let mut found = None;
+            for event in self.read_new(left)? {
+                if found.is_none() && matches(&event) {
+                    found = Some(event);
+                } else {
+                    self.defer(event);
+                }
+            }
+            if found.is_some() || left == Duration::from_secs(0) {
+                return Ok(found);
+            }
+        }
+    }
+
+    // Waits up to `timeout` for new events, ignoring the backlog.
+    pub fn read_new(&mut self, timeout: Duration) -> Result<Vec<InputEvent>> {
+        self.parser.read_events(self.tty.as_raw_fd(), timeout)
+    }
+
+    // Adds an event to the backlog, to be returned by `read_events`.
+    pub fn defer(&mut self, event: InputEvent) {
+        self.backlog.push_back(event);
+    }
+
+    pub fn take_backlog(&mut self) -> Vec<InputEvent> {
+        self.backlog.drain(..).collect()
+    }
+}
+
+impl AsRawFd for Reader {
+    fn as_raw_fd(&self) -> RawFd {
+        self.tty.as_raw_fd()
+    }
+}
+
+// Parses every event contained in a chunk of bytes read from the tty,
+// treating the end of the chunk as the end of any unfinished sequence.
+pub fn parse_input_events(bytes: &[u8]) -> Vec<InputEvent> {
+    let mut parser = Parser::new();
+    let mut events = parser.feed(bytes);
+    events.extend(parser.flush());
+    events
+}
 
 
 // Reference: redox-os/termion/blob/master/src/event.rs
@@ -25,6 +355,8 @@ where I: Iterator<Item = u8> {
                     }
                 }
                 Some(b'[') => parse_csi(iter),
+                Some(b']') => parse_osc(iter),
+                Some(b'P') => parse_dcs(iter),
                 // (imdaveho) NOTE: Since we are taking 12 bytes
                 // each time from /dev/tty, the iterator that results
                 // is always going to have zeroes. This causes ESC to
@@ -192,6 +524,30 @@ where I: Iterator<Item = u8> {
             };
             Mouse(event)
         },
+        // Match: DEC private replies
+        // ESC [ ? Ps ; ... c (DA1) or ESC [ ? Pd ; Ps $ y (DECRPM)
+        Some(b'?') => {
+            let mut buf = Vec::new();
+            let mut character = match iter.next() {
+                Some(ch) => ch,
+                None => return Unsupported,
+            };
+            while !(64..=126).contains(&character) {
+                buf.push(character);
+                character = match iter.next() {
+                    Some(ch) => ch,
+                    None => return Unsupported,
+                }
+            }
+            match character {
+                b'c' => parse_csi_device_attrs(buf),
+                b'y' if buf.last() == Some(&b'$') => {
+                    buf.pop();
+                    parse_csi_mode_report(buf)
+                }
+                _ => Unsupported,
+            }
+        },
         // Match: Numbered escape code.
         Some(c @ b'0'..=b'9') => {
             let mut buf = Vec::new();
@@ -224,6 +580,167 @@ where I: Iterator<Item = u8> {
     return input_event;
 }
 
+// Collects the body of an OSC or DCS string up to its terminator, which is
+// either BEL or ST (ESC \). Returns `None` if the string ends early.
+fn parse_string_body<I>(iter: &mut I) -> Option<Vec<u8>>
+where I: Iterator<Item = u8> {
+    let mut buf = Vec::new();
+    loop {
+        match iter.next()? {
+            b'\x07' => return Some(buf),
+            b'\x1B' => match iter.next()? {
+                b'\\' => return Some(buf),
+                _ => return None,
+            },
+            c => buf.push(c),
+        }
+    }
+}
+
+// Whether the bytes after ESC ] or ESC P start the way the replies that are
+// parsed do: digits then `;` for OSC, and `1+r`, `0+r` or `>|` for DCS.
+// `None` if there are too few bytes to tell.
+fn is_reply(intro: u8, tail: &[u8]) -> Option<bool> {
+    if intro == b']' {
+        let digits = tail.iter().take_while(|b| b.is_ascii_digit()).count();
+        return match tail.get(digits) {
+            Some(b';') => Some(digits > 0),
+            Some(_) => Some(false),
+            None => None,
+        };
+    }
+    let mut is_prefix = false;
+    for intro in [&b"1+r"[..], b"0+r", b">|"].iter() {
+        if tail.starts_with(intro) { return Some(true) }
+        is_prefix |= intro.starts_with(tail);
+    }
+    match is_prefix {
+        true => None,
+        false => Some(false),
+    }
+}
+
+// Match: OSC replies
+// ESC ] Ps ; Pt ST
+fn parse_osc<I>(iter: &mut I) -> InputEvent
+where I: Iterator<Item = u8> {
+    let mut iter = iter.peekable();
+    // Without anything following, this is Alt + ']'
+    if iter.peek().is_none() { return Keyboard(Alt(']')) }
+    let body = match parse_string_body(&mut iter)
+        .and_then(|buf| String::from_utf8(buf).ok()) {
+            Some(body) => body,
+            None => return Unsupported,
+    };
+    let mut parts = body.splitn(2, ';');
+    let (code, rest) = match (parts.next(), parts.next()) {
+        (Some(code), Some(rest)) => (code, rest),
+        _ => return Unsupported,
+    };
+    let (slot, spec) = match code {
+        "52" => return parse_clipboard(rest),
+        "10" => (ColorSlot::Foreground, rest),
+        "11" => (ColorSlot::Background, rest),
+        "12" => (ColorSlot::Cursor, rest),
+        "4" => {
+            let mut parts = rest.splitn(2, ';');
+            match (parts.next().map(str::parse), parts.next()) {
+                (Some(Ok(n)), Some(spec)) => (ColorSlot::Palette(n), spec),
+                _ => return Unsupported,
+            }
+        }
+        _ => return Unsupported,
+    };
+    match parse_xcolor(spec) {
+        Some(color) => ColorReport(slot, color),
+        None => Unsupported,
+    }
+}
+
+// OSC 52 ; Pc ; Pd where Pd is base64
+fn parse_clipboard(rest: &str) -> InputEvent {
+    let mut parts = rest.splitn(2, ';');
+    let target = match parts.next() {
+        Some(t) if t.starts_with('p') => ClipboardTarget::Primary,
+        Some(_) => ClipboardTarget::Clipboard,
+        None => return Unsupported,
+    };
+    match parts.next().and_then(base64::decode) {
+        Some(data) => {
+            Clipboard(target, String::from_utf8_lossy(&data).into_owned())
+        }
+        None => Unsupported,
+    }
+}
+
+// Parses an X11 color specification as used in OSC replies, eg.
+// `rgb:ffff/8080/0000` (1 to 4 hex digits per channel) or `#ff8000`.
+fn parse_xcolor(spec: &str) -> Option<Color> {
+    fn channel(hex: &str) -> Option<u8> {
+        if hex.is_empty() || hex.len() > 4 { return None }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1u32 << (4 * hex.len())) - 1;
+        Some(((value * 255 + max / 2) / max) as u8)
+    }
+    if let Some(rgb) = spec.strip_prefix("rgb:") {
+        let mut chans = rgb.split('/').map(channel);
+        return match (chans.next(), chans.next(), chans.next(), chans.next()) {
+            (Some(Some(r)), Some(Some(g)), Some(Some(b)), None) => {
+                Some(Color::Rgb { r, g, b })
+            }
+            _ => None,
+        };
+    }
+    let hex = spec.strip_prefix('#')?;
+    if hex.is_empty() || hex.len() % 3 != 0 { return None }
+    let n = hex.len() / 3;
+    Some(Color::Rgb {
+        r: channel(hex.get(..n)?)?,
+        g: channel(hex.get(n..2 * n)?)?,
+        b: channel(hex.get(2 * n..)?)?,
+    })
+}
+
+// Match: DCS replies
+// ESC P 1 + r Pt ST (XTGETTCAP)
+fn parse_dcs<I>(iter: &mut I) -> InputEvent
+where I: Iterator<Item = u8> {
+    let mut iter = iter.peekable();
+    // Without anything following, this is Alt + 'P'
+    if iter.peek().is_none() { return Keyboard(Alt('P')) }
+    let body = match parse_string_body(&mut iter)
+        .and_then(|buf| String::from_utf8(buf).ok()) {
+            Some(body) => body,
+            None => return Unsupported,
+    };
+    if let Some(cap) = body.strip_prefix("1+r") {
+        let mut parts = cap.splitn(2, '=');
+        let name = parts.next().and_then(decode_hex);
+        let value = parts.next().map(decode_hex);
+        return match (name, value) {
+            (Some(name), Some(Some(value))) => TermCap(name, Some(value)),
+            (Some(name), None) => TermCap(name, None),
+            _ => Unsupported,
+        };
+    }
+    if let Some(cap) = body.strip_prefix("0+r") {
+        return match decode_hex(cap) {
+            Some(name) => TermCap(name, None),
+            None => Unsupported,
+        };
+    }
+    Unsupported
+}
+
+fn decode_hex(hex: &str) -> Option<String> {
+    if hex.len() % 2 != 0 { return None }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
 fn parse_utf8_char<I>(c: u8, iter: &mut I) -> Option<char>
 where I: Iterator<Item = u8> {
     if c.is_ascii() {
@@ -250,12 +767,10 @@ where I: Iterator<Item = u8> {
 fn parse_csi_rxvt_mouse(buf: Vec<u8>) -> InputEvent {
     // rxvt mouse encoding:
     // ESC [ Cb ; Cx ; Cy ; M
-    let str_buf = String::from_utf8(buf).unwrap();
-
-    let nums: Vec<i16> = str_buf
-        .split(';')
-        .map(|n| n.parse().unwrap())
-        .collect();
+    let nums: Vec<i16> = match parse_csi_params(buf) {
+        Some(nums) if nums.len() >= 3 => nums,
+        _ => return Unsupported,
+    };
 
     let cb = nums[0];
     let cx = nums[1];
@@ -274,14 +789,12 @@ fn parse_csi_rxvt_mouse(buf: Vec<u8>) -> InputEvent {
 }
 
 fn parse_csi_special_key_code(buf: Vec<u8>) -> InputEvent {
-    let str_buf = String::from_utf8(buf).unwrap();
-
     // This CSI sequence can be a list of
     // semicolon-separated numbers.
-    let nums: Vec<u8> = str_buf
-        .split(';')
-        .map(|n| n.parse().unwrap())
-        .collect();
+    let nums: Vec<u8> = match parse_csi_params(buf) {
+        Some(nums) => nums,
+        None => return Unsupported,
+    };
 
     if nums.is_empty() { return Unsupported }
 
@@ -329,15 +842,43 @@ fn parse_csi_cursor_position(buf: Vec<u8>) -> InputEvent {
     // ESC [ Cy ; Cx R
     // Cy - cursor row number (starting from 1)
     // Cx - cursor column number (starting from 1)
-    let str_buf = String::from_utf8(buf).unwrap();
-
-    let nums: Vec<i16> = str_buf
-        .split(';')
-        .map(|n| n.parse().unwrap())
-        .collect();
+    let nums: Vec<i16> = match parse_csi_params(buf) {
+        Some(nums) if nums.len() == 2 => nums,
+        _ => return Unsupported,
+    };
 
     let row = nums[0] - 1;
     let col = nums[1] - 1;
 
     CursorPos(col, row)
 }
+
+fn parse_csi_device_attrs(buf: Vec<u8>) -> InputEvent {
+    // ESC [ ? Ps ; ... c
+    match parse_csi_params(buf) {
+        Some(attrs) => DeviceAttrs(attrs),
+        None => Unsupported,
+    }
+}
+
+fn parse_csi_mode_report(buf: Vec<u8>) -> InputEvent {
+    // ESC [ ? Pd ; Ps $ y
+    // Pd - the DEC private mode that was queried
+    // Ps - 0: not recognized, 1: set, 2: reset, 3: permanently set,
+    //      4: permanently reset
+    let nums: Vec<u16> = match parse_csi_params(buf) {
+        Some(nums) if nums.len() == 2 && nums[1] <= 4 => nums,
+        _ => return Unsupported,
+    };
+    ModeReport(nums[0], nums[1] as u8)
+}
+
+// Splits the semicolon-separated numbers of a CSI sequence. Returns `None`
+// if any of them is not a number of type `T`.
+fn parse_csi_params<T: std::str::FromStr>(buf: Vec<u8>) -> Option<Vec<T>> {
+    let str_buf = String::from_utf8(buf).ok()?;
+    str_buf
+        .split(';')
+        .map(|n| n.parse().ok())
+        .collect()
+}
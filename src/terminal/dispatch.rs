@@ -0,0 +1,330 @@
+// The dispatcher owns the thread that reads user input from the terminal and
+// broadcasts each parsed `InputEvent` to every subscribed `EventHandle`.
+//
+// Each handle has its own channel, so several threads can consume the same
+// stream of events independently. Handles can narrow what they receive by
+// `EventKind`, pause their own delivery, or `lock` the dispatcher so that
+// they become the only receiver (eg. while a modal prompt owns the keyboard).
+// The dispatcher itself can be suspended, which stops it from consuming any
+// input at all until it is resumed.
+
+use std::collections::HashMap;
+use std::io::{ Result, Error, ErrorKind };
+use std::sync::{
+    Arc, Mutex,
+    atomic::{ AtomicBool, Ordering },
+    mpsc::{ channel, Sender, Receiver },
+};
+use std::thread::{ self, JoinHandle };
+use std::time::Duration;
+use crate::common::enums::InputEvent;
+
+
+// How long the reader thread waits for input before checking whether it has
+// been suspended or shut down.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EventKind {
+    Keyboard,
+    Mouse,
+    CursorPos,
+    // Any other reply to a query (device attributes, modes, colors, etc).
+    Reply,
+    Unsupported,
+}
+
+impl EventKind {
+    pub fn of(event: &InputEvent) -> Self {
+        match event {
+            InputEvent::Keyboard(_) => EventKind::Keyboard,
+            InputEvent::Mouse(_) => EventKind::Mouse,
+            InputEvent::CursorPos(_, _) => EventKind::CursorPos,
+            InputEvent::DeviceAttrs(_)
+            | InputEvent::ModeReport(_, _)
+            | InputEvent::ColorReport(_, _)
+            | InputEvent::TermCap(_, _)
+            | InputEvent::Clipboard(_, _) => EventKind::Reply,
+            InputEvent::Unsupported => EventKind::Unsupported,
+        }
+    }
+
+    fn mask(kinds: &[EventKind]) -> u32 {
+        kinds.iter().fold(0, |acc, kind| acc | 1 << *kind as u32)
+    }
+}
+
+const ALL_KINDS: u32 = !0;
+
+
+struct Emitter {
+    event_tx: Sender<InputEvent>,
+    filter: u32,
+    is_suspended: bool,
+}
+
+struct Emitters {
+    map: HashMap<usize, Emitter>,
+    // The id of the handle that currently has exclusive delivery.
+    lock_owner: Option<usize>,
+    next_id: usize,
+}
+
+impl Emitters {
+    fn broadcast(&mut self, event: InputEvent) {
+        let bit = 1 << EventKind::of(&event) as u32;
+        let owner = self.lock_owner;
+        for (id, emitter) in self.map.iter() {
+            if matches!(owner, Some(o) if o != *id) { continue }
+            if emitter.is_suspended || emitter.filter & bit == 0 { continue }
+            // A failed send means the receiver was dropped while its handle
+            // is being torn down; that handle removes itself on drop.
+            let _ = emitter.event_tx.send(event.clone());
+        }
+    }
+}
+
+
+pub struct Dispatcher {
+    emitters: Arc<Mutex<Emitters>>,
+    is_running: Arc<AtomicBool>,
+    is_suspended: Arc<AtomicBool>,
+    // Set by the reader thread once it has seen the suspension and is no
+    // longer reading from the source.
+    is_idle: Arc<AtomicBool>,
+    input_handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl Dispatcher {
+    // Starts reading input from the controlling terminal.
+    pub fn init() -> Result<Self> {
+        #[cfg(unix)] {
+            use std::os::unix::io::AsRawFd;
+            use crate::parser::unix::{ open_tty, Parser };
+            let tty = open_tty()?;
+            let mut parser = Parser::new();
+            Ok(Self::with_source(move |timeout| {
+                parser.read_events(tty.as_raw_fd(), timeout)
+            }))
+        }
+
+        #[cfg(windows)] {
+            Ok(Self::with_source(|timeout| {
+                let (_, events) = crate::parser::windows::read_input_events();
+                if events.is_empty() { thread::sleep(timeout) }
+                Ok(events)
+            }))
+        }
+    }
+
+    // Starts dispatching events produced by `source`. The source is called
+    // repeatedly on the reader thread with the longest time it may block
+    // for; returning an error stops the dispatcher.
+    pub fn with_source<F>(mut source: F) -> Self
+    where F: FnMut(Duration) -> Result<Vec<InputEvent>> + Send + 'static {
+        let emitters = Arc::new(Mutex::new(Emitters {
+            map: HashMap::new(),
+            lock_owner: None,
+            next_id: 0,
+        }));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let is_suspended = Arc::new(AtomicBool::new(false));
+        let is_idle = Arc::new(AtomicBool::new(false));
+
+        let input_handle = {
+            let emitters = emitters.clone();
+            let is_running = is_running.clone();
+            let is_suspended = is_suspended.clone();
+            let is_idle = is_idle.clone();
+            thread::spawn(move || {
+                let mut outcome = Ok(());
+                while is_running.load(Ordering::SeqCst) {
+                    if is_suspended.load(Ordering::SeqCst) {
+                        is_idle.store(true, Ordering::SeqCst);
+                        thread::sleep(POLL_INTERVAL);
+                        continue
+                    }
+                    let events = match source(POLL_INTERVAL) {
+                        Ok(events) => events,
+                        Err(e) => { outcome = Err(e); break }
+                    };
+                    if events.is_empty() { continue }
+                    if let Ok(mut emitters) = emitters.lock() {
+                        for event in events { emitters.broadcast(event) }
+                    }
+                }
+                is_running.store(false, Ordering::SeqCst);
+                // Dropping every sender lets blocked handles return `None`.
+                if let Ok(mut emitters) = emitters.lock() {
+                    emitters.map.clear();
+                    emitters.lock_owner = None;
+                }
+                outcome
+            })
+        };
+
+        Self {
+            emitters,
+            is_running,
+            is_suspended,
+            is_idle,
+            input_handle: Some(input_handle),
+        }
+    }
+
+    // Subscribes to every kind of event.
+    pub fn listen(&self) -> EventHandle {
+        self.subscribe(ALL_KINDS)
+    }
+
+    // Subscribes to the given kinds of event only.
+    pub fn listen_for(&self, kinds: &[EventKind]) -> EventHandle {
+        self.subscribe(EventKind::mask(kinds))
+    }
+
+    fn subscribe(&self, filter: u32) -> EventHandle {
+        let (event_tx, event_rx) = channel();
+        let mut emitters = self.emitters.lock()
+            .expect("Error locking the dispatcher.");
+        let id = emitters.next_id;
+        emitters.next_id += 1;
+        // Once the reader thread has exited there is nothing to deliver;
+        // not registering the sender leaves the handle disconnected.
+        if self.is_running() {
+            emitters.map.insert(id, Emitter {
+                event_tx, filter, is_suspended: false });
+        }
+        EventHandle { id, event_rx, emitters: self.emitters.clone() }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running.load(Ordering::SeqCst)
+    }
+
+    // Stops consuming input so that something else can read the terminal
+    // directly. Input that arrives while suspended is left unread. This
+    // blocks until the reader thread has let go of the input source.
+    pub fn suspend(&self) {
+        self.is_suspended.store(true, Ordering::SeqCst);
+        while self.is_running() && !self.is_idle.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn resume(&self) {
+        self.is_idle.store(false, Ordering::SeqCst);
+        self.is_suspended.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.is_suspended.load(Ordering::SeqCst)
+    }
+
+    // Stops the reader thread and disconnects every handle. Returns the
+    // error that stopped the thread early, if any.
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.is_running.store(false, Ordering::SeqCst);
+        match self.input_handle.take() {
+            Some(handle) => match handle.join() {
+                Ok(outcome) => outcome,
+                Err(_) => Err(Error::new(
+                    ErrorKind::Other, "The input thread panicked.")),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+
+pub struct EventHandle {
+    id: usize,
+    event_rx: Receiver<InputEvent>,
+    emitters: Arc<Mutex<Emitters>>,
+}
+
+impl EventHandle {
+    // Returns the next event if one is ready, without blocking.
+    pub fn poll_async(&self) -> Option<InputEvent> {
+        self.event_rx.try_recv().ok()
+    }
+
+    // Drains every ready event and returns the most recent one.
+    pub fn poll_latest_async(&self) -> Option<InputEvent> {
+        self.event_rx.try_iter().last()
+    }
+
+    // Blocks until the next event. Returns `None` once the dispatcher has
+    // shut down.
+    pub fn poll_sync(&self) -> Option<InputEvent> {
+        self.event_rx.recv().ok()
+    }
+
+    pub fn poll_timeout(&self, timeout: Duration) -> Option<InputEvent> {
+        self.event_rx.recv_timeout(timeout).ok()
+    }
+
+    pub fn set_filter(&self, kinds: &[EventKind]) {
+        self.with_emitter(|e| e.filter = EventKind::mask(kinds));
+    }
+
+    pub fn clear_filter(&self) {
+        self.with_emitter(|e| e.filter = ALL_KINDS);
+    }
+
+    // Stops delivery to this handle. Events dispatched while suspended are
+    // dropped rather than queued.
+    pub fn suspend(&self) {
+        self.with_emitter(|e| e.is_suspended = true);
+    }
+
+    pub fn resume(&self) {
+        self.with_emitter(|e| e.is_suspended = false);
+    }
+
+    // Makes this handle the only one that receives events. Returns `false`
+    // if another handle already holds the lock.
+    pub fn lock(&self) -> bool {
+        let mut emitters = match self.emitters.lock() {
+            Ok(emitters) => emitters,
+            Err(_) => return false,
+        };
+        match emitters.lock_owner {
+            Some(owner) => owner == self.id,
+            None => { emitters.lock_owner = Some(self.id); true }
+        }
+    }
+
+    pub fn unlock(&self) {
+        if let Ok(mut emitters) = self.emitters.lock() {
+            if emitters.lock_owner == Some(self.id) {
+                emitters.lock_owner = None;
+            }
+        }
+    }
+
+    fn with_emitter<F: FnOnce(&mut Emitter)>(&self, f: F) {
+        if let Ok(mut emitters) = self.emitters.lock() {
+            if let Some(emitter) = emitters.map.get_mut(&self.id) {
+                f(emitter)
+            }
+        }
+    }
+}
+
+impl Drop for EventHandle {
+    fn drop(&mut self) {
+        if let Ok(mut emitters) = self.emitters.lock() {
+            emitters.map.remove(&self.id);
+            if emitters.lock_owner == Some(self.id) {
+                emitters.lock_owner = None;
+            }
+        }
+    }
+}
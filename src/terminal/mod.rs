@@ -2,8 +2,16 @@
 mod posix;
 #[cfg(windows)]
 mod win32;
+pub mod dispatch;
+#[cfg(unix)]
+pub mod stream;
+#[cfg(unix)]
+pub mod query;
 
 #[cfg(unix)]
 pub use posix::Term;
 #[cfg(windows)]
-pub use win32::Term;
\ No newline at end of file
+pub use win32::Term;
+
+#[cfg(test)]
+mod tests;
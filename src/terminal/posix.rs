@@ -1,14 +1,196 @@
-use std::io::{ Result, Error, ErrorKind };
-use libc::termios as Termios;
+use std::fs::{ File, OpenOptions };
+use std::io::{ Result, Error, ErrorKind, Write, stdout };
+use std::os::unix::io::{ AsRawFd, FromRawFd, RawFd };
+use std::sync::{
+    Arc, Mutex, MutexGuard,
+    atomic::{ AtomicBool, AtomicUsize, Ordering },
+};
+use std::time::Duration;
+use libc::{ termios as Termios, STDIN_FILENO, STDOUT_FILENO };
 use crate::actions::ansi::*;
-use crate::common::enums::{ Clear, Style, Color };
+use crate::common::color;
+use crate::common::enums::{
+    Clear, Style, TextStyle, Color, ColorDepth, ColorEncoding,
+    ColorSlot, CursorShape, ClipboardTarget, InputEvent, Query
+};
+use crate::parser::unix::Reader;
+use super::dispatch::Dispatcher;
+use super::query::{ lock_input, read_shared, Input, QueryHandle };
+use super::stream::EventStream;
 
 
-pub struct Term { mode: Termios }
+// How long `raw_pos` waits for the terminal to report the cursor position.
+const POS_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How long the first frame waits to find out whether the terminal supports
+// synchronized output.
+const SYNC_TIMEOUT: Duration = Duration::from_millis(200);
+
+// How long `background_luminance` and `set_color` wait for the terminal to
+// report a color.
+const COLOR_TIMEOUT: Duration = Duration::from_secs(1);
+
+// DEC mode for synchronized output.
+const SYNC_MODE: u16 = 2026;
+
+// A changed color slot and what it was before, if the terminal said.
+type ChangedColor = (ColorSlot, Option<Color>);
+
+
+struct Output {
+    buffer: output::OutputBuffer,
+    writer: Box<dyn Write + Send>,
+}
+
+impl Output {
+    fn flush(&mut self) -> Result<()> {
+        self.buffer.flush_into(&mut self.writer)
+    }
+}
+
+
+pub struct Term {
+    mode: Termios,
+    // The fd that raw mode is switched on, and the one the window size is
+    // read from. Both stay open for as long as the `Term` (they belong to
+    // stdio, or to its input and output).
+    mode_fd: RawFd,
+    size_fd: RawFd,
+    output: Mutex<Output>,
+    // For a `Term` over stdio, the tty is opened on first use, so that a
+    // `Term` that never reads input does not hold on to it.
+    input: Arc<Mutex<Option<Input>>>,
+    // Whether frames are wrapped in synchronized output brackets. `None`
+    // until the terminal has been asked.
+    sync: Mutex<Option<bool>>,
+    // Resetting the scroll region homes the cursor, so `close` only does it
+    // if a region was set.
+    has_region: AtomicBool,
+    // Set when `mark_pos` has to fall back to asking for the position. Holds
+    // the last marked position.
+    soft_mark: AtomicBool,
+    marked: Mutex<Option<(i16, i16)>>,
+    // Whether the cursor shape or color was changed, for `close` to undo.
+    has_cursor_style: AtomicBool,
+    // What the blinking was before it was first changed, taken to be the
+    // opposite of the change, for `close` to put back.
+    restore_blink: Mutex<Option<bool>>,
+    // How many titles were pushed and not popped yet, for `close` to pop.
+    pushed_titles: AtomicUsize,
+    // Whether a hyperlink is open, for `reset_styles` to close.
+    has_link: AtomicBool,
+    // The palette entries and default colors that were changed, with what
+    // they were before if the terminal said, for `close` to put back.
+    changed_colors: Mutex<Vec<ChangedColor>>,
+    // The style that the terminal has now, as far as this `Term` knows.
+    active: Mutex<TextStyle>,
+    // The styles that DECSC and entering the alternate screen saved, which
+    // the terminal puts back on DECRC and on leaving it.
+    marked_style: Mutex<TextStyle>,
+    alt_style: Mutex<TextStyle>,
+    colors: Mutex<Colors>,
+}
+
+// How colors are sent: brought down to `depth` first, then encoded.
+#[derive(Clone, Copy)]
+struct Colors {
+    depth: ColorDepth,
+    encoding: ColorEncoding,
+}
+
+impl Colors {
+    // Terminals without the 256 color palette may not understand the
+    // indexed form of the basic colors either.
+    fn detect() -> Self {
+        let depth = color::detect_depth();
+        let encoding = match depth < ColorDepth::Ansi256 {
+            true => ColorEncoding::Legacy,
+            false => ColorEncoding::Indexed,
+        };
+        Self { depth, encoding }
+    }
+}
 
 impl Term {
+    // Uses stdin for the terminal mode, stdout for the size and output, and
+    // /dev/tty for input.
     pub fn new() -> Result<Self> {
-        Ok(Self { mode: output::get_mode()? })
+        Ok(Self {
+            mode: output::get_mode_of(STDIN_FILENO)?,
+            mode_fd: STDIN_FILENO,
+            size_fd: STDOUT_FILENO,
+            output: Mutex::new(Output {
+                buffer: output::OutputBuffer::new(),
+                writer: Box::new(stdout()),
+            }),
+            input: Arc::new(Mutex::new(None)),
+            sync: Mutex::new(None),
+            has_region: AtomicBool::new(false),
+            soft_mark: AtomicBool::new(false),
+            marked: Mutex::new(None),
+            has_cursor_style: AtomicBool::new(false),
+            restore_blink: Mutex::new(None),
+            pushed_titles: AtomicUsize::new(0),
+            has_link: AtomicBool::new(false),
+            changed_colors: Mutex::new(Vec::new()),
+            active: Mutex::new(TextStyle::default()),
+            marked_style: Mutex::new(TextStyle::default()),
+            alt_style: Mutex::new(TextStyle::default()),
+            colors: Mutex::new(Colors::detect()),
+        })
+    }
+
+    // Does everything through /dev/tty, so that stdin and stdout stay free
+    // for the program's data (eg. a picker whose output is piped).
+    pub fn tty() -> Result<Self> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        Self::from_file(tty)
+    }
+
+    // Does everything through a duplicate of `fd`, which must refer to a
+    // terminal. The caller keeps ownership of `fd` itself.
+    pub fn from_fd(fd: RawFd) -> Result<Self> {
+        let dup = unsafe { libc::dup(fd) };
+        if dup == -1 { return Err(Error::last_os_error()) }
+        Self::from_file(unsafe { File::from_raw_fd(dup) })
+    }
+
+    fn from_file(tty: File) -> Result<Self> {
+        Self::with_io(tty.try_clone()?, tty)
+    }
+
+    // Reads input from `reader` and writes output to `writer`. Raw mode is
+    // switched on the reader's terminal and the size is read from the
+    // writer's, the same way stdin and stdout split them for `new`.
+    pub fn with_io<R, W>(reader: R, writer: W) -> Result<Self>
+    where
+        R: AsRawFd + Send + 'static,
+        W: Write + AsRawFd + Send + 'static,
+    {
+        let (mode_fd, size_fd) = (reader.as_raw_fd(), writer.as_raw_fd());
+        Ok(Self {
+            mode: output::get_mode_of(mode_fd)?,
+            mode_fd, size_fd,
+            output: Mutex::new(Output {
+                buffer: output::OutputBuffer::new(),
+                writer: Box::new(writer),
+            }),
+            input: Arc::new(Mutex::new(Some(
+                Input::from_reader(Reader::from_source(reader))))),
+            sync: Mutex::new(None),
+            has_region: AtomicBool::new(false),
+            soft_mark: AtomicBool::new(false),
+            marked: Mutex::new(None),
+            has_cursor_style: AtomicBool::new(false),
+            restore_blink: Mutex::new(None),
+            pushed_titles: AtomicUsize::new(0),
+            has_link: AtomicBool::new(false),
+            changed_colors: Mutex::new(Vec::new()),
+            active: Mutex::new(TextStyle::default()),
+            marked_style: Mutex::new(TextStyle::default()),
+            alt_style: Mutex::new(TextStyle::default()),
+            colors: Mutex::new(Colors::detect()),
+        })
     }
 
     // CURSOR FUNCTIONS
@@ -16,163 +198,675 @@ impl Term {
         let (mut col, mut row) = (col, row);
         if col < 0 { col = col.abs() }
         if row < 0 { row = row.abs() }
-        output::prints(&cursor::goto(col, row))
+        self.queue(&cursor::Goto(col, row))
     }
 
     pub fn up(&self, n: i16) -> Result<()> {
         let mut n = n;
         if n < 0 { n = n.abs() }
-        output::prints(&cursor::move_up(n))
+        self.queue(&cursor::MoveUp(n))
     }
 
     pub fn down(&self, n: i16) -> Result<()> {
         let mut n = n;
         if n < 0 { n = n.abs() }
-        output::prints(&cursor::move_down(n))
+        self.queue(&cursor::MoveDown(n))
     }
 
     pub fn left(&self, n: i16) -> Result<()> {
         let mut n = n;
         if n < 0 { n = n.abs() }
-        output::prints(&cursor::move_left(n))
+        self.queue(&cursor::MoveLeft(n))
     }
 
     pub fn right(&self, n: i16) -> Result<()> {
         let mut n = n;
         if n < 0 { n = n.abs() }
-        output::prints(&cursor::move_right(n))
+        self.queue(&cursor::MoveRight(n))
     }
 
     pub fn query_pos(&self) -> Result<()> {
-        output::printf(&cursor::pos())
+        self.queue(&cursor::Pos)?;
+        self.flush()
     }
 
     pub fn raw_pos(&self) -> Result<(i16, i16)> {
-        // Where is the cursor?
-        // Use `ESC [ 6 n`.
-        let mut stdout = std::io::stdout();
-        let stdin = std::io::stdin();
-
-        // Write command
-        std::io::Write::write_all(&mut stdout, b"\x1B[6n")?;
-        std::io::Write::flush(&mut stdout)?;
-        std::io::BufRead::read_until(&mut stdin.lock(), b'[', &mut vec![])?;
-        let mut rows = vec![];
-        std::io::BufRead::read_until(&mut stdin.lock(), b';', &mut rows)?;
-        let mut cols = vec![];
-        std::io::BufRead::read_until(&mut stdin.lock(),  b'R', &mut cols)?;
-        // Remove delimiter
-        rows.pop(); cols.pop();
-
-        let parsed_rows = match rows
-            .into_iter()
-            .map(|b| (b as char))
-            .fold(String::new(), |mut acc, n| {
-                acc.push(n); acc
-            }).parse::<i16>() {
-                Ok(i) => i,
-                Err(e) => {
-                    let err_msg = format!(
-                        "Error parsing into i16. {:?}", e);
-                    return Err(Error::new(ErrorKind::Other, err_msg));
-                }
-            };
+        self.request_pos(POS_TIMEOUT)
+    }
 
-        let parsed_cols = match cols
-            .into_iter()
-            .map(|b| (b as char))
-            .fold(String::new(), |mut acc, n| {
-                acc.push(n); acc
-            }).parse::<i16>() {
-                Ok(i) => i,
-                Err(e) => {
-                    let err_msg = format!(
-                        "Error parsing into i16. {:?}", e);
-                    return Err(Error::new(ErrorKind::Other, err_msg));
-                }
-            };
+    // Asks the terminal where the cursor is and waits up to `timeout` for
+    // the answer. Input that arrives in the meantime is kept and returned by
+    // the next `read_events`, or passed on by the `Dispatcher` or
+    // `EventStream` that this `Term` made. One made with `Dispatcher::init`
+    // or `EventStream::new` reads the tty on its own: suspend it first so
+    // that it does not consume the reply.
+    pub fn request_pos(&self, timeout: Duration) -> Result<(i16, i16)> {
+        match self.query(Query::CursorPos, timeout)?.wait() {
+            Ok(InputEvent::CursorPos(col, row)) => Ok((col, row)),
+            Ok(_) => Err(Error::new(
+                ErrorKind::InvalidData, "Unexpected reply to the query.")),
+            Err(e) if e.kind() == ErrorKind::TimedOut => Err(Error::new(
+                ErrorKind::TimedOut,
+                "The terminal did not report the cursor position.")),
+            Err(e) => Err(e),
+        }
+    }
 
-        Ok((parsed_cols.saturating_sub(1) , parsed_rows.saturating_sub(1)))
+    // Saves the cursor position and text attributes (DECSC). With the
+    // software fallback on, only the position is saved, by asking the
+    // terminal for it (see `request_pos`).
+    pub fn mark_pos(&self) -> Result<()> {
+        if !self.soft_mark.load(Ordering::SeqCst) {
+            self.save_style(&self.marked_style)?;
+            return self.queue(&cursor::MarkPos);
+        }
+        let pos = self.raw_pos()?;
+        *self.marked()? = Some(pos);
+        Ok(())
+    }
+
+    // Goes back to what `mark_pos` saved (DECRC).
+    pub fn load_pos(&self) -> Result<()> {
+        if !self.soft_mark.load(Ordering::SeqCst) {
+            self.load_style(&self.marked_style)?;
+            return self.queue(&cursor::LoadPos);
+        }
+        let pos = *self.marked()?;
+        match pos {
+            Some((col, row)) => self.queue(&cursor::Goto(col, row)),
+            None => Err(Error::new(
+                ErrorKind::NotFound, "No cursor position was marked.")),
+        }
+    }
+
+    // For terminals that ignore DECSC/DECRC.
+    pub fn set_soft_mark(&self, enabled: bool) {
+        self.soft_mark.store(enabled, Ordering::SeqCst);
+    }
+
+    fn marked(&self) -> Result<MutexGuard<'_, Option<(i16, i16)>>> {
+        self.marked.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The marked position was poisoned by a panic."))
     }
 
     pub fn hide_cursor(&self) -> Result<()> {
-        output::prints(&cursor::hide_cursor())
+        self.queue(&cursor::HideCursor)
     }
 
     pub fn show_cursor(&self) -> Result<()> {
-        output::prints(&cursor::show_cursor())
+        self.queue(&cursor::ShowCursor)
+    }
+
+    pub fn set_cursor_shape(&self, shape: CursorShape) -> Result<()> {
+        self.has_cursor_style.store(true, Ordering::SeqCst);
+        self.queue(&cursor::SetShape(shape))
+    }
+
+    pub fn enable_blink(&self) -> Result<()> {
+        self.restore_blink()?.get_or_insert(false);
+        self.queue(&cursor::EnableBlink)
+    }
+
+    pub fn disable_blink(&self) -> Result<()> {
+        self.restore_blink()?.get_or_insert(true);
+        self.queue(&cursor::DisableBlink)
+    }
+
+    fn restore_blink(&self) -> Result<MutexGuard<'_, Option<bool>>> {
+        self.restore_blink.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The blink setting was poisoned by a panic."))
+    }
+
+    pub fn set_cursor_color(&self, color: Color) -> Result<()> {
+        self.has_cursor_style.store(true, Ordering::SeqCst);
+        self.queue(&cursor::SetColor(color))
+    }
+
+    pub fn reset_cursor_color(&self) -> Result<()> {
+        self.queue(&cursor::ResetColor)
     }
 
     // SCREEN FUNCTIONS
     pub fn clear(&self, method: Clear) -> Result<()> {
-        output::prints(&screen::clear(method))
+        self.queue(&screen::ClearScreen(method))
     }
 
     pub fn size(&self) -> Result<(i16, i16)> {
-        screen::size()
+        screen::size_of(self.size_fd)
     }
 
     pub fn resize(&self, w: i16, h: i16) -> Result<()> {
-        output::printf(&screen::resize(w, h))
+        self.queue(&screen::Resize(w, h))?;
+        self.flush()
+    }
+
+    // Confines scrolling to the rows from `top` to `bottom` (inclusive).
+    // The cursor moves to the top left corner of the screen.
+    pub fn set_scroll_region(&self, top: i16, bottom: i16) -> Result<()> {
+        let (top, bottom) = (top.saturating_abs(), bottom.saturating_abs());
+        if top >= bottom {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                "The scroll region needs at least two rows."));
+        }
+        self.has_region.store(true, Ordering::SeqCst);
+        self.queue(&screen::SetScrollRegion(top, bottom))
+    }
+
+    pub fn reset_scroll_region(&self) -> Result<()> {
+        self.has_region.store(false, Ordering::SeqCst);
+        self.queue(&screen::ResetScrollRegion)
+    }
+
+    pub fn scroll_up(&self, n: i16) -> Result<()> {
+        // Terminals take a count of 0 to mean 1.
+        if n == 0 { return Ok(()) }
+        self.queue(&screen::ScrollUp(n.saturating_abs()))
+    }
+
+    pub fn scroll_down(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        self.queue(&screen::ScrollDown(n.saturating_abs()))
+    }
+
+    pub fn reverse_index(&self) -> Result<()> {
+        self.queue(&screen::ReverseIndex)
+    }
+
+    // WINDOW FUNCTIONS
+    pub fn set_title(&self, title: &str) -> Result<()> {
+        self.queue(&screen::SetTitle(title))
+    }
+
+    pub fn set_icon_name(&self, name: &str) -> Result<()> {
+        self.queue(&screen::SetIconName(name))
+    }
+
+    pub fn set_title_and_icon(&self, title: &str) -> Result<()> {
+        self.queue(&screen::SetTitleAndIcon(title))
+    }
+
+    // Saves the current title and icon name, to be put back by `pop_title`
+    // (or by `close`).
+    pub fn push_title(&self) -> Result<()> {
+        self.pushed_titles.fetch_add(1, Ordering::SeqCst);
+        self.queue(&screen::PushTitle)
+    }
+
+    // Restores the last pushed title and icon name. Does nothing if every
+    // title this `Term` pushed has been popped already.
+    pub fn pop_title(&self) -> Result<()> {
+        let popped = self.pushed_titles.fetch_update(
+            Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        match popped {
+            Ok(_) => self.queue(&screen::PopTitle),
+            Err(_) => Ok(()),
+        }
+    }
+
+    // EDITING FUNCTIONS
+    pub fn insert_lines(&self, n: i16) -> Result<()> {
+        // Terminals take a count of 0 to mean 1.
+        if n == 0 { return Ok(()) }
+        self.queue(&screen::InsertLines(n.saturating_abs()))
+    }
+
+    pub fn delete_lines(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        self.queue(&screen::DeleteLines(n.saturating_abs()))
+    }
+
+    pub fn insert_chars(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        self.queue(&screen::InsertChars(n.saturating_abs()))
+    }
+
+    pub fn delete_chars(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        self.queue(&screen::DeleteChars(n.saturating_abs()))
+    }
+
+    pub fn erase_chars(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        self.queue(&screen::EraseChars(n.saturating_abs()))
     }
 
     pub fn enable_alt(&self) -> Result<()> {
-        output::printf(&screen::enable_alt())
+        self.save_style(&self.alt_style)?;
+        self.queue(&screen::EnableAlt)?;
+        self.flush()
     }
 
     pub fn disable_alt(&self) -> Result<()> {
-        output::printf(&screen::disable_alt())
+        self.load_style(&self.alt_style)?;
+        self.queue(&screen::DisableAlt)?;
+        self.flush()
     }
 
     // OUTPUT FUNCTIONS
+    // Adds `content` to the output buffer. Nothing reaches the terminal
+    // until `flush` (or the buffer passes its auto flush threshold).
     pub fn prints(&self, content: &str) -> Result<()> {
-        output::prints(content)
+        self.buffered(|buf| buf.push_str(content))
     }
 
     pub fn printf(&self, content: &str) -> Result<()> {
-        output::printf(content)
+        self.prints(content)?;
+        self.flush()
     }
 
+    // Sends everything buffered so far to the terminal in a single write.
     pub fn flush(&self) -> Result<()> {
-        output::flush()
+        self.output()?.flush()
+    }
+
+    // Adds a command to the output buffer.
+    pub fn queue(&self, command: &dyn Command) -> Result<()> {
+        self.buffered(|buf| buf.queue(command))
+    }
+
+    // Makes room for a frame of `capacity` bytes, and raises the auto flush
+    // threshold to match so that such a frame goes out in a single write.
+    pub fn set_capacity(&self, capacity: usize) -> Result<()> {
+        let buffer = &mut self.output()?.buffer;
+        buffer.reserve(capacity);
+        if buffer.auto_flush() < capacity {
+            buffer.set_auto_flush(capacity);
+        }
+        Ok(())
+    }
+
+    // Flush automatically once `threshold` bytes are buffered. Use
+    // `usize::MAX` to only ever flush explicitly.
+    pub fn set_auto_flush(&self, threshold: usize) -> Result<()> {
+        self.output()?.buffer.set_auto_flush(threshold);
+        Ok(())
+    }
+
+    // FRAME FUNCTIONS
+    // Starts a frame. If the terminal supports synchronized output, nothing
+    // written until `end_frame` is shown before the whole frame is there.
+    pub fn begin_frame(&self) -> Result<()> {
+        match self.supports_sync() {
+            true => self.queue(&screen::BeginSync),
+            false => Ok(()),
+        }
+    }
+
+    // Ends the frame and flushes it.
+    pub fn end_frame(&self) -> Result<()> {
+        if self.supports_sync() {
+            self.queue(&screen::EndSync)?;
+        }
+        self.flush()
+    }
+
+    // Draws a frame with `draw` between `begin_frame` and `end_frame`.
+    pub fn frame<F>(&self, draw: F) -> Result<()>
+    where F: FnOnce(&Self) -> Result<()> {
+        self.begin_frame()?;
+        let drawn = draw(self);
+        // Always close the brackets, or the terminal stops repainting until
+        // its own timeout.
+        self.end_frame()?;
+        drawn
+    }
+
+    // Asks the terminal via DECRQM whether it supports synchronized output,
+    // the first time only. A terminal that does not answer in time is taken
+    // not to. The request skips the output buffer, so nothing queued so far
+    // goes out early; call this before the first frame to keep the wait out
+    // of it, or use `set_sync` to skip asking.
+    pub fn supports_sync(&self) -> bool {
+        let mut sync = match self.sync.lock() {
+            Ok(sync) => sync,
+            Err(_) => return false,
+        };
+        *sync.get_or_insert_with(|| {
+            let reply = self.probe(Query::Mode(SYNC_MODE), SYNC_TIMEOUT)
+                .and_then(|handle| handle.wait());
+            // 1 (set) and 2 (reset) mean the mode is known and can be
+            // switched; 3 (permanently set) makes the brackets harmless.
+            matches!(reply,
+                Ok(InputEvent::ModeReport(SYNC_MODE, 1..=3)))
+        })
+    }
+
+    // Skips detection and turns the synchronized output brackets on or off.
+    pub fn set_sync(&self, enabled: bool) -> Result<()> {
+        let mut sync = self.sync.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The sync setting was poisoned by a panic."))?;
+        *sync = Some(enabled);
+        Ok(())
+    }
+
+    fn buffered<F>(&self, f: F) -> Result<()>
+    where F: FnOnce(&mut output::OutputBuffer) {
+        let mut output = self.output()?;
+        f(&mut output.buffer);
+        if output.buffer.needs_flush() {
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn output(&self) -> Result<MutexGuard<'_, Output>> {
+        self.output.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The output buffer was poisoned by a panic."))
     }
 
     pub fn raw(&self) -> Result<()> {
-        output::enable_raw()
+        output::enable_raw_of(self.mode_fd)
     }
 
     pub fn cook(&self) -> Result<()> {
-        output::set_mode(&self.mode)
+        output::set_mode_of(self.mode_fd, &self.mode)
+    }
+
+    // INPUT FUNCTIONS
+    // Returns the events kept back while waiting for a reply, or else waits
+    // up to `timeout` for new ones. Replies to pending queries are not
+    // included; they go to their `QueryHandle`.
+    pub fn read_events(&self, timeout: Duration) -> Result<Vec<InputEvent>> {
+        let mut input = self.input()?;
+        input.as_mut().expect("The tty reader is missing.")
+            .read_events(timeout)
+    }
+
+    // Sends `query` followed by a DA1 sentinel and returns a handle that
+    // resolves to the reply, or fails once `timeout` has passed or the
+    // sentinel shows that the terminal ignored the query. Other input read
+    // while waiting is kept (see `request_pos`).
+    pub fn query(
+        &self, query: Query, timeout: Duration
+    ) -> Result<QueryHandle> {
+        self.send_query(query, timeout, true)
+    }
+
+    // Like `query`, but writes straight to the terminal and leaves whatever
+    // is buffered for the caller's own flush, so that asking in the middle
+    // of a frame does not split it.
+    fn probe(&self, query: Query, timeout: Duration) -> Result<QueryHandle> {
+        self.send_query(query, timeout, false)
+    }
+
+    fn send_query(
+        &self, query: Query, timeout: Duration, flush: bool
+    ) -> Result<QueryHandle> {
+        let mut input = self.input()?;
+        let input = input.as_mut().expect("The tty reader is missing.");
+        let mut output = self.output()?;
+        let sentinel = query != Query::DeviceAttrs;
+        if flush {
+            output.buffer.queue(&query::Request(&query));
+            if sentinel {
+                output.buffer.queue(&query::DeviceAttrs);
+            }
+            output.flush()?;
+        } else {
+            let mut request = Vec::new();
+            query::Request(&query).write_io(&mut request)?;
+            if sentinel {
+                query::DeviceAttrs.write_io(&mut request)?;
+            }
+            output.writer.write_all(&request)?;
+            output.writer.flush()?;
+        }
+        let id = input.register(query);
+        Ok(QueryHandle::new(id, timeout, self.input.clone()))
+    }
+
+    // Starts a `Dispatcher` that reads through this `Term`, so that replies
+    // to queries reach their `QueryHandle` while it runs, and input kept
+    // while waiting for one reaches its listeners.
+    pub fn dispatcher(&self) -> Result<Dispatcher> {
+        drop(self.input()?);
+        let input = self.input.clone();
+        Ok(Dispatcher::with_source(move |timeout| {
+            read_shared(&input, timeout)
+        }))
+    }
+
+    // An `EventStream` that reads through this `Term` (see `dispatcher`).
+    pub fn events(&self) -> Result<EventStream> {
+        drop(self.input()?);
+        EventStream::from_input(self.input.clone())
+    }
+
+    fn input(&self) -> Result<MutexGuard<'_, Option<Input>>> {
+        let mut input = lock_input(&self.input)?;
+        if input.is_none() {
+            *input = Some(Input::new()?);
+        }
+        Ok(input)
+    }
+
+    // CLIPBOARD FUNCTIONS
+    // Copies `content` to the user's clipboard. Large contents are encoded
+    // a chunk at a time, and go out in several writes once they pass the
+    // auto flush threshold. Content longer than `clipboard::MAX_CONTENT`
+    // bytes is refused with `ErrorKind::InvalidInput`.
+    pub fn set_clipboard(
+        &self, target: ClipboardTarget, content: &str
+    ) -> Result<()> {
+        if content.len() > clipboard::MAX_CONTENT {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                "The content is too large for the clipboard."));
+        }
+        self.queue(&clipboard::BeginCopy(target))?;
+        for chunk in content.as_bytes().chunks(clipboard::CHUNK_SIZE) {
+            self.queue(&clipboard::CopyData(chunk))?;
+        }
+        self.queue(&clipboard::EndCopy)?;
+        self.flush()
+    }
+
+    // Asks for the contents of the clipboard. If the terminal allows it,
+    // they arrive as an `InputEvent::Clipboard` among the other input.
+    pub fn request_clipboard(&self, target: ClipboardTarget) -> Result<()> {
+        self.queue(&clipboard::RequestClipboard(target))?;
+        self.flush()
     }
 
     // MOUSE FUNCTIONS
     pub fn enable_mouse(&self) -> Result<()> {
-        output::prints(&mouse::enable_mouse_mode())
+        self.queue(&mouse::EnableMouse)
     }
 
     pub fn disable_mouse(&self) -> Result<()> {
-        output::prints(&mouse::disable_mouse_mode())
+        self.queue(&mouse::DisableMouse)
     }
 
     // STYLE FUNCTIONS
+    // Only what differs from the active style is sent.
     pub fn set_fx(&self, effects: u32) -> Result<()> {
-        output::prints(&style::set_style(Style::Fx(effects)))
+        self.restyle(|active| active.with(Style::Fx(effects)))
     }
 
     pub fn set_fg(&self, color: Color) -> Result<()> {
-        output::prints(&style::set_style(Style::Fg(color)))
+        self.restyle(|active| active.with(Style::Fg(color)))
     }
 
     pub fn set_bg(&self, color: Color) -> Result<()> {
-        output::prints(&style::set_style(Style::Bg(color)))
+        self.restyle(|active| active.with(Style::Bg(color)))
+    }
+
+    pub fn set_ul_color(&self, color: Color) -> Result<()> {
+        self.restyle(|active| active.with(Style::Ul(color)))
     }
 
     pub fn set_styles(&self, fg: Color, bg: Color, fx: u32) -> Result<()> {
-        output::prints(&style::set_styles(fg, bg, fx))
+        self.restyle(|active| active
+            .with(Style::Fg(fg))
+            .with(Style::Bg(bg))
+            .with(Style::Fx(fx)))
+    }
+
+    // Switches to `style` as a whole, turning off whatever it leaves out.
+    pub fn set_style(&self, style: TextStyle) -> Result<()> {
+        self.restyle(|_| style)
+    }
+
+    pub fn style(&self) -> Result<TextStyle> {
+        Ok(*self.active()?)
     }
 
+    // Always sends the reset, so it also brings the terminal back in line
+    // after styles were printed directly. Also closes a hyperlink that is
+    // still open.
     pub fn reset_styles(&self) -> Result<()> {
-        output::prints(&style::reset())
+        self.end_link()?;
+        *self.active()? = TextStyle::default();
+        self.queue(&style::ResetStyle)
+    }
+
+    // Colors set from now on are replaced by the nearest ones the terminal
+    // can show at `depth`. Starts out as `color::detect_depth` found.
+    pub fn set_color_depth(&self, depth: ColorDepth) -> Result<()> {
+        self.colors()?.depth = depth;
+        Ok(())
+    }
+
+    pub fn color_depth(&self) -> Result<ColorDepth> {
+        Ok(self.colors()?.depth)
+    }
+
+    // Whether the basic colors are sent as palette entries or with the
+    // classic codes. Starts out `Legacy` for terminals with fewer than 256
+    // colors.
+    pub fn set_color_encoding(&self, encoding: ColorEncoding) -> Result<()> {
+        self.colors()?.encoding = encoding;
+        Ok(())
+    }
+
+    pub fn color_encoding(&self) -> Result<ColorEncoding> {
+        Ok(self.colors()?.encoding)
+    }
+
+    // PALETTE FUNCTIONS
+    // Changes a palette entry or default color of the terminal. The first
+    // change to a slot asks the terminal for its color, which `close` puts
+    // back. A slot whose color the terminal does not report is reset to
+    // the terminal's default instead.
+    pub fn set_color(&self, slot: ColorSlot, color: Color) -> Result<()> {
+        if slot == ColorSlot::Cursor {
+            return self.set_cursor_color(color);
+        }
+        let is_changed = |changed: &[(ColorSlot, Option<Color>)]| {
+            changed.iter().any(|(s, _)| *s == slot)
+        };
+        if !is_changed(&self.changed_colors()?) {
+            let reply = self.probe(Query::Color(slot), COLOR_TIMEOUT)
+                .and_then(|handle| handle.wait());
+            let original = match reply {
+                Ok(InputEvent::ColorReport(_, color)) => Some(color),
+                _ => None,
+            };
+            let mut changed = self.changed_colors()?;
+            if !is_changed(&changed) {
+                changed.push((slot, original));
+            }
+        }
+        self.queue(&palette::SetColor(slot, color))
+    }
+
+    pub fn reset_color(&self, slot: ColorSlot) -> Result<()> {
+        self.changed_colors()?.retain(|(s, _)| *s != slot);
+        self.queue(&palette::ResetColor(slot))
+    }
+
+    fn changed_colors(&self) -> Result<MutexGuard<'_, Vec<ChangedColor>>> {
+        self.changed_colors.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The changed colors were poisoned by a panic."))
+    }
+
+    // Asks the terminal for the color in `slot` (see `request_pos` about
+    // the wait and a running `Dispatcher`).
+    pub fn request_color(
+        &self, slot: ColorSlot, timeout: Duration
+    ) -> Result<Color> {
+        match self.query(Query::Color(slot), timeout)?.wait() {
+            Ok(InputEvent::ColorReport(_, color)) => Ok(color),
+            Ok(_) => Err(Error::new(
+                ErrorKind::InvalidData, "Unexpected reply to the query.")),
+            Err(e) if e.kind() == ErrorKind::TimedOut => Err(Error::new(
+                ErrorKind::TimedOut, "The terminal did not report the color.")),
+            Err(e) => Err(e),
+        }
+    }
+
+    // The relative luminance of the background, from 0 (black) to 1
+    // (white). Luminance is not linear in how light a color looks: the
+    // background is dark below about 0.18 (see `is_dark_background`).
+    pub fn background_luminance(&self) -> Result<f64> {
+        let color = self.request_color(ColorSlot::Background, COLOR_TIMEOUT)?;
+        color.luminance().ok_or_else(|| Error::new(
+            ErrorKind::InvalidData, "The terminal reported no color."))
+    }
+
+    // Whether white text reads better on the background than black text
+    // does, that is, whether its luminance is below about 0.18.
+    pub fn is_dark_background(&self) -> Result<bool> {
+        let luminance = self.background_luminance()?;
+        // Where the contrast with black, (l + 0.05) / 0.05, equals the
+        // contrast with white, 1.05 / (l + 0.05).
+        Ok(luminance < (1.05f64 * 0.05).sqrt() - 0.05)
+    }
+
+    fn colors(&self) -> Result<MutexGuard<'_, Colors>> {
+        self.colors.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The color settings were poisoned by a panic."))
+    }
+
+    fn restyle<F>(&self, f: F) -> Result<()>
+    where F: FnOnce(TextStyle) -> TextStyle {
+        let Colors { depth, encoding } = *self.colors()?;
+        let mut active = self.active()?;
+        let next = f(*active);
+        let next = TextStyle {
+            fg: next.fg.downsample(depth),
+            bg: next.bg.downsample(depth),
+            ul: next.ul.downsample(depth),
+            ..next
+        };
+        self.queue(&style::Transition(*active, next, encoding))?;
+        *active = next;
+        Ok(())
+    }
+
+    // Only track what the terminal does by itself when it saves and
+    // restores the cursor; nothing is sent.
+    fn save_style(&self, saved: &Mutex<TextStyle>) -> Result<()> {
+        let active = *self.active()?;
+        *saved.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The saved style was poisoned by a panic."))? = active;
+        Ok(())
+    }
+
+    fn load_style(&self, saved: &Mutex<TextStyle>) -> Result<()> {
+        let style = *saved.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The saved style was poisoned by a panic."))?;
+        *self.active()? = style;
+        Ok(())
+    }
+
+    fn active(&self) -> Result<MutexGuard<'_, TextStyle>> {
+        self.active.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The active style was poisoned by a panic."))
+    }
+
+    // Makes everything printed until `end_link` a hyperlink to `url`. The
+    // link may span several prints. Runs of text that share an `id` are
+    // highlighted together.
+    pub fn begin_link(&self, url: &str, id: Option<&str>) -> Result<()> {
+        self.has_link.store(true, Ordering::SeqCst);
+        self.queue(&style::BeginLink { url, id })
+    }
+
+    pub fn end_link(&self) -> Result<()> {
+        match self.has_link.swap(false, Ordering::SeqCst) {
+            true => self.queue(&style::EndLink),
+            false => Ok(()),
+        }
     }
 
     // CONFIG FUNCTIONS
@@ -185,12 +879,33 @@ impl Term {
     }
 
     pub fn close(&self) -> Result<()> {
+        if self.has_region.load(Ordering::SeqCst) {
+            self.reset_scroll_region()?;
+        }
         self.disable_alt()?;
         self.cook()?;
         self.disable_mouse()?;
         self.show_cursor()?;
+        if self.has_cursor_style.swap(false, Ordering::SeqCst) {
+            self.queue(&cursor::SetShape(CursorShape::Default))?;
+            self.queue(&cursor::ResetColor)?;
+        }
+        match self.restore_blink()?.take() {
+            Some(true) => self.queue(&cursor::EnableBlink)?,
+            Some(false) => self.queue(&cursor::DisableBlink)?,
+            None => (),
+        }
+        for (slot, original) in self.changed_colors()?.drain(..) {
+            match original {
+                Some(color) => self.queue(&palette::SetColor(slot, color))?,
+                None => self.queue(&palette::ResetColor(slot))?,
+            }
+        }
+        for _ in 0..self.pushed_titles.swap(0, Ordering::SeqCst) {
+            self.queue(&screen::PopTitle)?;
+        }
         self.reset_styles()?;
-        Ok(())
+        self.flush()
     }
 }
 
@@ -0,0 +1,256 @@
+// Correlates queries written to the terminal with the replies that come back
+// on its input.
+//
+// Terminals answer queries in the order they were sent, and every terminal
+// answers DA1. So each query is followed by a DA1 request that serves as a
+// sentinel: once the sentinel's answer arrives, a query that is still
+// unanswered is not supported by the terminal and fails right away instead
+// of waiting out its timeout.
+//
+// A query that times out (or whose handle is dropped) leaves the queue right
+// away, but its sentinel may still be on the way. Until it arrives, or until
+// `STALE_GRACE` has passed, the next DA1 answer and any late reply are taken
+// to be its and dropped, so they cannot answer the queries after it.
+//
+// The `Input` is shared: a `Dispatcher` or `EventStream` made by the `Term`
+// reads through it too (see `read_shared`), so that whoever reads the tty
+// hands replies to the waiting `QueryHandle`s and passes everything else on.
+
+use std::collections::{ HashMap, VecDeque };
+use std::io::{ Result, Error, ErrorKind };
+use std::os::unix::io::{ AsRawFd, RawFd };
+use std::sync::{ Arc, Mutex, MutexGuard };
+use std::task::Waker;
+use std::time::{ Duration, Instant };
+use crate::common::enums::{ InputEvent, Query };
+use crate::parser::unix::{ wait_readable, Reader };
+
+
+// How long to wait for the sentinel of an abandoned query before giving up
+// on it.
+const STALE_GRACE: Duration = Duration::from_secs(1);
+
+struct Pending {
+    id: usize,
+    query: Query,
+    reply: Option<InputEvent>,
+}
+
+// The input side of a `Term`: the tty reader plus the queries that are
+// waiting for a reply.
+pub(crate) struct Input {
+    reader: Reader,
+    pending: VecDeque<Pending>,
+    // Finished queries. `None` means the terminal did not answer.
+    resolved: HashMap<usize, Option<InputEvent>>,
+    // The abandoned queries whose sentinel is still due, oldest first: when
+    // to stop waiting for it, and the query if its reply is due too.
+    stale: VecDeque<(Instant, Option<Query>)>,
+    next_id: usize,
+    // The task of an `EventStream` that found nothing to read. Input kept
+    // while waiting for a reply does not make the tty readable, so it is
+    // woken once the wait is over.
+    waker: Option<Waker>,
+}
+
+impl Input {
+    pub(crate) fn new() -> Result<Self> {
+        Ok(Self::from_reader(Reader::new()?))
+    }
+
+    pub(crate) fn from_reader(reader: Reader) -> Self {
+        Self {
+            reader,
+            pending: VecDeque::new(),
+            resolved: HashMap::new(),
+            stale: VecDeque::new(),
+            next_id: 0,
+            waker: None,
+        }
+    }
+
+    // Records a query that has been (or is about to be) written, followed by
+    // its sentinel unless it is a DA1 query itself.
+    pub(crate) fn register(&mut self, query: Query) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push_back(Pending { id, query, reply: None });
+        id
+    }
+
+    // Returns the events that are not replies to a pending query, or else
+    // waits up to `timeout` for new ones.
+    pub(crate) fn read_events(
+        &mut self, timeout: Duration
+    ) -> Result<Vec<InputEvent>> {
+        let events = self.reader.read_events(timeout)?;
+        if self.pending.is_empty() && self.stale.is_empty() {
+            return Ok(events)
+        }
+        for event in events { self.route(event) }
+        Ok(self.reader.take_backlog())
+    }
+
+    // How long until `read_events` has something to return without new
+    // input (see `Reader::timeout`).
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.reader.timeout()
+    }
+
+    pub(crate) fn set_waker(&mut self, waker: &Waker) {
+        match &self.waker {
+            Some(current) if current.will_wake(waker) => (),
+            _ => self.waker = Some(waker.clone()),
+        }
+    }
+
+    fn route(&mut self, event: InputEvent) {
+        let now = Instant::now();
+        while let Some((expiry, _)) = self.stale.front() {
+            if *expiry > now { break }
+            self.stale.pop_front();
+        }
+        if let InputEvent::DeviceAttrs(_) = event {
+            // The sentinel of an abandoned query comes before any that
+            // are still pending.
+            if self.stale.pop_front().is_some() { return }
+            // Either the answer to a DA1 query, or the sentinel of the
+            // oldest query (which has had its chance to answer by now).
+            match self.pending.pop_front() {
+                Some(front) => {
+                    let reply = match front.query {
+                        Query::DeviceAttrs => Some(event),
+                        _ => front.reply,
+                    };
+                    self.resolved.insert(front.id, reply);
+                }
+                None => self.reader.defer(event),
+            }
+            return
+        }
+        // Replies come in order, so a reply that an abandoned query still
+        // had due is its own.
+        let late = |(_, due): &&mut (Instant, Option<Query>)| {
+            matches!(due, Some(query) if event.answers(query))
+        };
+        if let Some((_, due)) = self.stale.iter_mut().find(late) {
+            *due = None;
+            return
+        }
+        match self.pending.iter_mut()
+            .find(|p| p.reply.is_none() && event.answers(&p.query)) {
+                Some(pending) => pending.reply = Some(event),
+                None => self.reader.defer(event),
+        }
+    }
+
+    fn wait(&mut self, id: usize, deadline: Instant) -> Result<InputEvent> {
+        let reply = self.wait_reply(id, deadline);
+        if self.reader.timeout() == Some(Duration::from_secs(0)) {
+            if let Some(waker) = self.waker.take() { waker.wake() }
+        }
+        reply
+    }
+
+    fn wait_reply(
+        &mut self, id: usize, deadline: Instant
+    ) -> Result<InputEvent> {
+        loop {
+            if let Some(reply) = self.resolved.remove(&id) {
+                return reply.ok_or_else(|| Error::new(
+                    ErrorKind::Unsupported,
+                    "The terminal does not support this query."));
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            for event in self.reader.read_new(left)? { self.route(event) }
+            if left == Duration::from_secs(0)
+            && !self.resolved.contains_key(&id) {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "The terminal did not answer the query."));
+            }
+        }
+    }
+
+    fn abandon(&mut self, id: usize) {
+        self.resolved.remove(&id);
+        let at = self.pending.iter().position(|p| p.id == id);
+        if let Some(pending) = at.and_then(|at| self.pending.remove(at)) {
+            let due = match (pending.query, pending.reply) {
+                (Query::DeviceAttrs, _) | (_, Some(_)) => None,
+                (query, None) => Some(query),
+            };
+            self.stale.push_back((Instant::now() + STALE_GRACE, due));
+        }
+    }
+}
+
+impl AsRawFd for Input {
+    fn as_raw_fd(&self) -> RawFd {
+        self.reader.as_raw_fd()
+    }
+}
+
+
+// Reads through a shared `Input` for up to `timeout`, without holding the
+// lock while waiting for the tty, so that a `QueryHandle` can take over at
+// any point. Replies go to their handles; the rest is returned.
+pub(crate) fn read_shared(
+    input: &Mutex<Option<Input>>, timeout: Duration
+) -> Result<Vec<InputEvent>> {
+    let (fd, wait) = {
+        let input = lock_input(input)?;
+        let input = input.as_ref().ok_or_else(closed)?;
+        match input.timeout() {
+            Some(t) if t < timeout => (input.as_raw_fd(), t),
+            _ => (input.as_raw_fd(), timeout),
+        }
+    };
+    if wait > Duration::from_secs(0) { wait_readable(fd, wait)?; }
+    lock_input(input)?.as_mut().ok_or_else(closed)?
+        .read_events(Duration::from_secs(0))
+}
+
+pub(crate) fn lock_input(
+    input: &Mutex<Option<Input>>
+) -> Result<MutexGuard<'_, Option<Input>>> {
+    input.lock().map_err(|_| Error::new(
+        ErrorKind::Other, "The tty reader was poisoned by a panic."))
+}
+
+pub(crate) fn closed() -> Error {
+    Error::new(ErrorKind::NotConnected, "The tty reader is closed.")
+}
+
+
+// A query that has been sent to the terminal. `wait` resolves it to the
+// matching reply.
+pub struct QueryHandle {
+    id: usize,
+    deadline: Instant,
+    input: Arc<Mutex<Option<Input>>>,
+}
+
+impl QueryHandle {
+    pub(crate) fn new(
+        id: usize, timeout: Duration, input: Arc<Mutex<Option<Input>>>
+    ) -> Self {
+        Self { id, deadline: Instant::now() + timeout, input }
+    }
+
+    // Blocks until the reply arrives. Fails with `ErrorKind::Unsupported` if
+    // the terminal does not answer this kind of query, or with
+    // `ErrorKind::TimedOut` if the terminal does not answer at all.
+    pub fn wait(self) -> Result<InputEvent> {
+        lock_input(&self.input)?.as_mut().ok_or_else(closed)?
+            .wait(self.id, self.deadline)
+    }
+}
+
+impl Drop for QueryHandle {
+    fn drop(&mut self) {
+        if let Ok(mut input) = self.input.lock() {
+            if let Some(input) = input.as_mut() { input.abandon(self.id) }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+// An asynchronous stream of `InputEvent`s that does not depend on any
+// particular async runtime.
+//
+// The tty is opened in non-blocking mode and read directly from `poll_next`.
+// When there is nothing to read, the task's `Waker` is handed to a small
+// notifier thread that waits for the tty to become readable (or for a held
+// back ESC to time out) and then wakes the task. No thread ever blocks on
+// behalf of the executor, and any executor that understands `std::task` can
+// drive the stream.
+//
+// `poll_next` has the same shape as `futures::Stream::poll_next`, so the
+// stream can be adapted with `futures::stream::poll_fn` if need be.
+//
+// A stream made by `Term::events` reads through the `Term`'s input instead,
+// so that replies to its queries are not lost. If a `QueryHandle` is busy
+// with the input, the stream does not wait for it but tries again shortly.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::future::Future;
+use std::io::{ Read, Result, Error, ErrorKind };
+use std::os::unix::io::{ AsRawFd, RawFd };
+use std::pin::Pin;
+use std::sync::{ Arc, Condvar, Mutex, TryLockError };
+use std::task::{ Context, Poll, Waker };
+use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, Instant };
+use libc::{
+    c_void, fcntl, pipe, poll, pollfd,
+    F_GETFL, F_SETFL, O_NONBLOCK, POLLIN
+};
+use crate::common::enums::InputEvent;
+use crate::parser::unix::{ open_tty, poll_millis, Parser };
+use super::query::{ closed, lock_input, Input };
+
+
+// How soon to try again when the shared input is busy.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+enum Source {
+    Tty(File, Parser),
+    Shared(Arc<Mutex<Option<Input>>>),
+}
+
+enum Fill {
+    Read,
+    // Nothing to read; try again once the tty is readable or the timeout
+    // has passed.
+    Idle(Option<Duration>),
+}
+
+
+pub struct EventStream {
+    // Declared first so the notifier thread stops before the tty is closed.
+    notifier: Notifier,
+    source: Source,
+    pending: VecDeque<InputEvent>,
+    is_done: bool,
+}
+
+impl EventStream {
+    // Opens the controlling terminal for reading.
+    pub fn new() -> Result<Self> {
+        Self::from_file(open_tty()?)
+    }
+
+    // Reads events from an already opened tty (or any readable file). The
+    // file is switched to non-blocking mode.
+    pub fn from_file(tty: File) -> Result<Self> {
+        let fd = tty.as_raw_fd();
+        unsafe {
+            let flags = fcntl(fd, F_GETFL);
+            if flags == -1 || fcntl(fd, F_SETFL, flags | O_NONBLOCK) == -1 {
+                return Err(Error::last_os_error());
+            }
+        }
+        Self::with_source(fd, Source::Tty(tty, Parser::new()))
+    }
+
+    pub(crate) fn from_input(
+        input: Arc<Mutex<Option<Input>>>
+    ) -> Result<Self> {
+        let fd = lock_input(&input)?.as_ref().ok_or_else(closed)?
+            .as_raw_fd();
+        Self::with_source(fd, Source::Shared(input))
+    }
+
+    fn with_source(fd: RawFd, source: Source) -> Result<Self> {
+        Ok(Self {
+            notifier: Notifier::spawn(fd)?,
+            source,
+            pending: VecDeque::new(),
+            is_done: false,
+        })
+    }
+
+    // Returns the next event if one is available. Otherwise the task is
+    // woken once the tty has more input. `None` means the tty was closed.
+    pub fn poll_next(
+        &mut self, cx: &mut Context<'_>
+    ) -> Poll<Option<Result<InputEvent>>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if self.is_done { return Poll::Ready(None) }
+
+            match self.fill(cx.waker()) {
+                Ok(Fill::Read) => (),
+                Ok(Fill::Idle(timeout)) => {
+                    self.notifier.register(cx.waker(), timeout);
+                    return Poll::Pending;
+                }
+                Err(e) => {
+                    self.is_done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self, waker: &Waker) -> Result<Fill> {
+        let (tty, parser) = match &mut self.source {
+            Source::Tty(tty, parser) => (tty, parser),
+            Source::Shared(input) => {
+                let mut input = match input.try_lock() {
+                    Ok(input) => input,
+                    Err(TryLockError::WouldBlock) =>
+                        return Ok(Fill::Idle(Some(RETRY_INTERVAL))),
+                    Err(TryLockError::Poisoned(_)) => return Err(Error::new(
+                        ErrorKind::Other,
+                        "The tty reader was poisoned by a panic.")),
+                };
+                let input = input.as_mut().ok_or_else(closed)?;
+                return match input.read_events(Duration::from_secs(0)) {
+                    Ok(events) if events.is_empty() => {
+                        input.set_waker(waker);
+                        Ok(Fill::Idle(input.timeout()))
+                    }
+                    Ok(events) => {
+                        self.pending.extend(events);
+                        Ok(Fill::Read)
+                    }
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                        self.is_done = true;
+                        Ok(Fill::Read)
+                    }
+                    Err(e) => Err(e),
+                };
+            }
+        };
+        let mut buf = [0u8; 1024];
+        match tty.read(&mut buf) {
+            Ok(0) => {
+                self.pending.extend(parser.flush());
+                self.is_done = true;
+            }
+            Ok(n) => self.pending.extend(parser.feed(&buf[..n])),
+            Err(e) => match e.kind() {
+                ErrorKind::Interrupted => (),
+                ErrorKind::WouldBlock => {
+                    let flushed = parser.flush_timeout();
+                    if flushed.is_empty() {
+                        return Ok(Fill::Idle(parser.timeout()));
+                    }
+                    self.pending.extend(flushed);
+                }
+                _ => return Err(e),
+            },
+        }
+        Ok(Fill::Read)
+    }
+
+    // Returns a future that resolves to the next event.
+    pub fn next_event(&mut self) -> Next<'_> {
+        Next { stream: self }
+    }
+}
+
+
+pub struct Next<'a> {
+    stream: &'a mut EventStream,
+}
+
+impl Future for Next<'_> {
+    type Output = Option<Result<InputEvent>>;
+
+    fn poll(
+        mut self: Pin<&mut Self>, cx: &mut Context<'_>
+    ) -> Poll<Self::Output> {
+        self.stream.poll_next(cx)
+    }
+}
+
+
+struct Readiness {
+    waker: Option<Waker>,
+    // When to wake the task even if the tty stays quiet.
+    deadline: Option<Instant>,
+    is_closed: bool,
+}
+
+// Waits for the tty to become readable on behalf of a pending task. The
+// self-pipe interrupts a `poll` that is already in progress, so that the
+// thread picks up a new deadline, or sees that it was closed.
+struct Notifier {
+    state: Arc<(Mutex<Readiness>, Condvar)>,
+    wake_pipe: (RawFd, RawFd),
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    fn spawn(fd: RawFd) -> Result<Self> {
+        let mut fds = [0; 2];
+        if unsafe { pipe(fds.as_mut_ptr()) } == -1 {
+            return Err(Error::last_os_error());
+        }
+        let wake_pipe = (fds[0], fds[1]);
+        // Neither end may block: the thread drains whatever is there, and
+        // a full pipe already wakes it.
+        for fd in fds.iter() {
+            unsafe {
+                let flags = fcntl(*fd, F_GETFL);
+                if flags == -1
+                || fcntl(*fd, F_SETFL, flags | O_NONBLOCK) == -1 {
+                    let err = Error::last_os_error();
+                    libc::close(fds[0]);
+                    libc::close(fds[1]);
+                    return Err(err);
+                }
+            }
+        }
+        let state = Arc::new((
+            Mutex::new(Readiness {
+                waker: None, deadline: None, is_closed: false }),
+            Condvar::new()));
+
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || loop {
+                // Sleep until a task is actually waiting for input.
+                let deadline = {
+                    let (lock, cvar) = &*state;
+                    let mut ready = match lock.lock() {
+                        Ok(ready) => ready,
+                        Err(_) => return,
+                    };
+                    while ready.waker.is_none() && !ready.is_closed {
+                        ready = match cvar.wait(ready) {
+                            Ok(ready) => ready,
+                            Err(_) => return,
+                        };
+                    }
+                    if ready.is_closed { return }
+                    ready.deadline
+                };
+                let millis = match deadline {
+                    Some(at) => poll_millis(
+                        at.saturating_duration_since(Instant::now())),
+                    None => -1,
+                };
+
+                let mut pfds = [
+                    pollfd { fd, events: POLLIN, revents: 0 },
+                    pollfd { fd: wake_pipe.0, events: POLLIN, revents: 0 },
+                ];
+                if unsafe { poll(pfds.as_mut_ptr(), 2, millis) } == -1
+                && Error::last_os_error().kind() == ErrorKind::Interrupted {
+                    continue
+                }
+                if pfds[1].revents != 0 {
+                    let mut buf = [0u8; 64];
+                    while unsafe { libc::read(wake_pipe.0,
+                        buf.as_mut_ptr() as *mut c_void, buf.len()) } > 0 {}
+                    // Closed, or the deadline changed: read the state again
+                    // unless the tty has something too.
+                    if pfds[0].revents == 0 { continue }
+                }
+
+                // Readable, hung up, errored or timed out: in every case the
+                // task needs to read the tty again to find out which.
+                let waker = match state.0.lock() {
+                    Ok(mut ready) => ready.waker.take(),
+                    Err(_) => return,
+                };
+                if let Some(waker) = waker { waker.wake() }
+            })
+        };
+
+        Ok(Self { state, wake_pipe, handle: Some(handle) })
+    }
+
+    fn register(&self, waker: &Waker, timeout: Option<Duration>) {
+        let (lock, cvar) = &*self.state;
+        if let Ok(mut ready) = lock.lock() {
+            let deadline = timeout.map(|t| Instant::now() + t);
+            // A thread that is already polling would wait for the old
+            // deadline. Waking up early for a later one does no harm.
+            let is_sooner = match (ready.deadline, deadline) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(old), Some(new)) => new < old,
+            };
+            if ready.waker.is_some() && is_sooner { self.interrupt() }
+            ready.deadline = deadline;
+            match &ready.waker {
+                Some(current) if current.will_wake(waker) => (),
+                _ => ready.waker = Some(waker.clone()),
+            }
+            cvar.notify_one();
+        }
+    }
+
+    fn interrupt(&self) {
+        unsafe {
+            libc::write(self.wake_pipe.1, b"\0".as_ptr() as *const c_void, 1);
+        }
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.state;
+        if let Ok(mut ready) = lock.lock() {
+            ready.is_closed = true;
+            cvar.notify_one();
+        }
+        self.interrupt();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        unsafe {
+            libc::close(self.wake_pipe.0);
+            libc::close(self.wake_pipe.1);
+        }
+    }
+}
@@ -0,0 +1,609 @@
+use std::sync::mpsc::{ channel, Sender };
+use std::time::Duration;
+use crate::common::enums::{ InputEvent, KeyEvent, MouseEvent };
+use super::dispatch::{ Dispatcher, EventKind };
+
+
+const WAIT: Duration = Duration::from_millis(500);
+
+fn fake_dispatcher() -> (Dispatcher, Sender<InputEvent>) {
+    let (tx, rx) = channel();
+    let dispatcher = Dispatcher::with_source(move |timeout| {
+        Ok(rx.recv_timeout(timeout).into_iter().collect())
+    });
+    (dispatcher, tx)
+}
+
+fn is_char(event: Option<InputEvent>, ch: char) -> bool {
+    match event {
+        Some(InputEvent::Keyboard(KeyEvent::Char(c))) => c == ch,
+        _ => false,
+    }
+}
+
+#[test]
+fn test_dispatch_broadcast_and_filter() {
+    let (dispatcher, tx) = fake_dispatcher();
+    let all = dispatcher.listen();
+    let mice = dispatcher.listen_for(&[EventKind::Mouse]);
+
+    tx.send(InputEvent::Keyboard(KeyEvent::Char('a'))).unwrap();
+    tx.send(InputEvent::Mouse(MouseEvent::Release(1, 2))).unwrap();
+
+    assert!(is_char(all.poll_timeout(WAIT), 'a'));
+    match all.poll_timeout(WAIT) {
+        Some(InputEvent::Mouse(MouseEvent::Release(1, 2))) => (),
+        _ => panic!("expected a mouse release"),
+    }
+    match mice.poll_timeout(WAIT) {
+        Some(InputEvent::Mouse(MouseEvent::Release(1, 2))) => (),
+        _ => panic!("expected a mouse release"),
+    }
+    assert!(mice.poll_async().is_none());
+}
+
+#[test]
+fn test_dispatch_lock_and_suspend() {
+    let (dispatcher, tx) = fake_dispatcher();
+    let first = dispatcher.listen();
+    let modal = dispatcher.listen();
+
+    assert!(modal.lock());
+    assert!(!first.lock());
+    tx.send(InputEvent::Keyboard(KeyEvent::Char('y'))).unwrap();
+    assert!(is_char(modal.poll_timeout(WAIT), 'y'));
+    modal.unlock();
+
+    modal.suspend();
+    tx.send(InputEvent::Keyboard(KeyEvent::Char('n'))).unwrap();
+    assert!(is_char(first.poll_timeout(WAIT), 'n'));
+    assert!(first.poll_async().is_none());
+    assert!(modal.poll_async().is_none());
+}
+
+#[test]
+fn test_dispatch_shutdown() {
+    let (mut dispatcher, _tx) = fake_dispatcher();
+    let handle = dispatcher.listen();
+    dispatcher.suspend();
+    assert!(dispatcher.is_suspended());
+    dispatcher.resume();
+
+    dispatcher.shutdown().unwrap();
+    assert!(!dispatcher.is_running());
+    assert!(handle.poll_sync().is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_event_stream_wakes_on_input() {
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::Arc;
+    use std::task::{ Context, Poll, Wake, Waker };
+    use std::thread;
+    use super::stream::EventStream;
+
+    struct Unpark(thread::Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) { self.0.unpark() }
+    }
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let (reader, mut writer) = unsafe {
+        (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    let mut stream = EventStream::from_file(reader).unwrap();
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    assert!(stream.poll_next(&mut cx).is_pending());
+
+    writer.write_all(b"q\x1B[A").unwrap();
+    let mut events = Vec::new();
+    while events.len() < 2 {
+        match stream.poll_next(&mut cx) {
+            Poll::Ready(Some(Ok(event))) => events.push(event),
+            Poll::Ready(_) => panic!("the stream ended early"),
+            Poll::Pending => thread::park_timeout(WAIT),
+        }
+    }
+    assert!(is_char(Some(events.remove(0)), 'q'));
+    match events[0] {
+        InputEvent::Keyboard(KeyEvent::Up) => (),
+        _ => panic!("expected the up arrow"),
+    }
+
+    drop(writer);
+    loop {
+        match stream.poll_next(&mut cx) {
+            Poll::Ready(None) => break,
+            Poll::Ready(Some(_)) => panic!("expected the end of the stream"),
+            Poll::Pending => thread::park_timeout(WAIT),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_query_sentinel_routing() {
+    use std::fs::File;
+    use std::io::{ ErrorKind, Write };
+    use std::os::unix::io::FromRawFd;
+    use std::sync::{ Arc, Mutex };
+    use crate::common::enums::Query;
+    use crate::parser::unix::Reader;
+    use super::query::{ Input, QueryHandle };
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let (tty, mut writer) = unsafe {
+        (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    let input = Input::from_reader(Reader::from_file(tty));
+    let input = Arc::new(Mutex::new(Some(input)));
+
+    let register = |query| {
+        let mut guard = input.lock().unwrap();
+        let id = guard.as_mut().unwrap().register(query);
+        QueryHandle::new(id, WAIT, input.clone())
+    };
+    let pos = register(Query::CursorPos);
+    let mode = register(Query::Mode(2026));
+    let attrs = register(Query::DeviceAttrs);
+
+    // CPR + sentinel, a key press, the sentinel for the unanswered mode
+    // query, then the answer to the DA1 query.
+    writer.write_all(b"\x1B[3;7R\x1B[?62c").unwrap();
+    writer.write_all(b"z\x1B[?62c\x1B[?64;4c").unwrap();
+
+    match mode.wait() {
+        Err(e) => assert_eq!(e.kind(), ErrorKind::Unsupported),
+        Ok(_) => panic!("expected the mode query to be unsupported"),
+    }
+    match pos.wait() {
+        Ok(InputEvent::CursorPos(6, 2)) => (),
+        _ => panic!("expected a cursor position report"),
+    }
+    match attrs.wait() {
+        Ok(InputEvent::DeviceAttrs(a)) => assert_eq!(a, vec![64, 4]),
+        _ => panic!("expected device attributes"),
+    }
+    let mut guard = input.lock().unwrap();
+    let events = guard.as_mut().unwrap()
+        .read_events(Duration::from_secs(0)).unwrap();
+    assert_eq!(events.len(), 1);
+    assert!(is_char(events.into_iter().next(), 'z'));
+    drop(guard);
+
+    let lost = register(Query::CursorPos);
+    match lost.wait() {
+        Err(e) => assert_eq!(e.kind(), ErrorKind::TimedOut),
+        Ok(_) => panic!("expected the query to time out"),
+    }
+
+    // The late answer to the lost query and its sentinel do not answer the
+    // next one.
+    let pos = register(Query::CursorPos);
+    writer.write_all(b"\x1B[1;1R\x1B[?62c\x1B[9;9R\x1B[?62c").unwrap();
+    match pos.wait() {
+        Ok(InputEvent::CursorPos(8, 8)) => (),
+        _ => panic!("expected the second cursor position report"),
+    }
+    let mut guard = input.lock().unwrap();
+    let events = guard.as_mut().unwrap()
+        .read_events(Duration::from_secs(0)).unwrap();
+    assert!(events.is_empty());
+}
+
+// Returns the master and slave ends of a new pty.
+#[cfg(unix)]
+fn open_pty() -> (std::fs::File, std::fs::File) {
+    use std::ffi::CStr;
+    use std::fs::{ File, OpenOptions };
+    use std::os::unix::io::{ AsRawFd, FromRawFd };
+
+    let master = unsafe {
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        assert!(fd >= 0);
+        assert_eq!(libc::grantpt(fd), 0);
+        assert_eq!(libc::unlockpt(fd), 0);
+        File::from_raw_fd(fd)
+    };
+    let name = unsafe { CStr::from_ptr(libc::ptsname(master.as_raw_fd())) };
+    let slave = OpenOptions::new().read(true).write(true)
+        .open(name.to_str().unwrap()).unwrap();
+    (master, slave)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_term_over_pty() {
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+    use crate::actions::ansi::output;
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let size = libc::winsize {
+        ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 };
+    assert_eq!(unsafe {
+        libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) }, 0);
+
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    assert_eq!(term.size().unwrap(), (80, 24));
+
+    term.raw().unwrap();
+    let mode = output::get_mode_of(slave.as_raw_fd()).unwrap();
+    assert_eq!(mode.c_lflag & libc::ICANON, 0);
+
+    term.prints("ab").unwrap();
+    term.printf("c").unwrap();
+    let mut buf = [0u8; 16];
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"abc");
+
+    term.cook().unwrap();
+    let mode = output::get_mode_of(slave.as_raw_fd()).unwrap();
+    assert_ne!(mode.c_lflag & libc::ICANON, 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_clipboard_limit() {
+    use std::io::{ ErrorKind, Read };
+    use std::os::unix::io::AsRawFd;
+    use std::thread;
+    use super::posix::Term;
+    use crate::actions::ansi::clipboard::MAX_CONTENT;
+    use crate::common::enums::ClipboardTarget;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+
+    // Too much content is refused before anything is written.
+    let content = "a".repeat(MAX_CONTENT + 1);
+    let err = term.set_clipboard(ClipboardTarget::Clipboard, &content)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    // Read while writing, since the payload does not fit in the pty.
+    let reader = thread::spawn(move || {
+        let mut out = Vec::new();
+        let mut buf = [0u8; 4096];
+        while !out.ends_with(b"\x1B\\") {
+            let n = master.read(&mut buf).unwrap();
+            out.extend_from_slice(&buf[..n]);
+        }
+        (master, out)
+    });
+    term.set_clipboard(ClipboardTarget::Clipboard, &content[1..]).unwrap();
+    let (master, out) = reader.join().unwrap();
+    assert!(out.starts_with(b"\x1B]52;c;YWFh"));
+    assert_eq!(out.len(), "\x1B]52;c;".len() + MAX_CONTENT / 3 * 4 + 2);
+    drop(term);
+    drop(master);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sync_frame() {
+    use std::io::{ Read, Write };
+    use std::os::unix::io::AsRawFd;
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+
+    // The terminal's answer to DECRQM 2026, then the DA1 sentinel.
+    // Output queued before the frame stays buffered while asking.
+    master.write_all(b"\x1B[?2026;2$y\x1B[?62c").unwrap();
+    term.prints("a").unwrap();
+    term.frame(|t| t.prints("hi")).unwrap();
+    assert!(term.supports_sync());
+
+    let mut out = Vec::new();
+    let expected: &[u8] = b"\x1B[?2026$p\x1B[ca\x1B[?2026hhi\x1B[?2026l";
+    let mut buf = [0u8; 64];
+    while out.len() < expected.len() {
+        let n = master.read(&mut buf).unwrap();
+        out.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(out, expected);
+
+    term.set_sync(false).unwrap();
+    term.frame(|t| t.prints("ho")).unwrap();
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"ho");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_query_while_dispatching() {
+    use std::io::{ Read, Write };
+    use std::os::unix::io::AsRawFd;
+    use std::thread;
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+    let dispatcher = term.dispatcher().unwrap();
+    let handle = dispatcher.listen();
+
+    // Answer once the query is out, with keys on either side of the reply.
+    let answer = thread::spawn(move || {
+        let expected: &[u8] = b"\x1B[6n\x1B[c";
+        let (mut out, mut buf) = (Vec::new(), [0u8; 16]);
+        while out.len() < expected.len() {
+            let n = master.read(&mut buf).unwrap();
+            out.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(out, expected);
+        master.write_all(b"a\x1B[5;3R\x1B[?62cb").unwrap();
+        master
+    });
+    assert_eq!(term.request_pos(Duration::from_secs(2)).unwrap(), (2, 4));
+    let master = answer.join().unwrap();
+
+    let timeout = Duration::from_secs(1);
+    assert!(is_char(handle.poll_timeout(timeout), 'a'));
+    assert!(is_char(handle.poll_timeout(timeout), 'b'));
+    assert!(handle.poll_timeout(Duration::from_millis(100)).is_none());
+    drop(term);
+    drop(master);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_event_stream_sooner_deadline() {
+    use std::os::unix::io::AsRawFd;
+    use std::sync::{ Arc, atomic::{ AtomicUsize, Ordering } };
+    use std::task::{ Context, Wake, Waker };
+    use std::thread;
+    use super::posix::Term;
+
+    struct Count(AtomicUsize);
+    impl Wake for Count {
+        fn wake(self: Arc<Self>) { self.0.fetch_add(1, Ordering::SeqCst); }
+    }
+
+    let (_master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    let mut stream = term.events().unwrap();
+    let count = Arc::new(Count(AtomicUsize::new(0)));
+    let waker = Waker::from(count.clone());
+    let mut cx = Context::from_waker(&waker);
+    // Nothing to read and no deadline: the notifier polls without one.
+    assert!(stream.poll_next(&mut cx).is_pending());
+
+    thread::scope(|s| {
+        s.spawn(|| term.request_pos(Duration::from_millis(500)));
+        thread::sleep(Duration::from_millis(50));
+        // The query holds the input, so the stream asks to be woken soon.
+        assert!(stream.poll_next(&mut cx).is_pending());
+        thread::sleep(Duration::from_millis(100));
+        assert!(count.0.load(Ordering::SeqCst) > 0);
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn test_soft_mark() {
+    use std::io::{ Read, Write };
+    use std::os::unix::io::AsRawFd;
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+
+    term.mark_pos().unwrap();
+    term.load_pos().unwrap();
+    term.flush().unwrap();
+    let mut buf = [0u8; 64];
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"\x1B7\x1B8");
+
+    term.set_soft_mark(true);
+    master.write_all(b"\x1B[5;3R\x1B[?62c").unwrap();
+    term.mark_pos().unwrap();
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"\x1B[6n\x1B[c");
+    term.load_pos().unwrap();
+    term.flush().unwrap();
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"\x1B[5;3H");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_style_restored_with_cursor() {
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+    use crate::common::enums::{ Color, ColorDepth };
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+    term.set_color_depth(ColorDepth::Ansi16).unwrap();
+
+    // DECRC and leaving the alternate screen bring back the style that was
+    // saved, so a color set since then has to be sent again.
+    term.mark_pos().unwrap();
+    term.set_fg(Color::Blue).unwrap();
+    term.load_pos().unwrap();
+    term.set_fg(Color::Blue).unwrap();
+    term.flush().unwrap();
+    let mut buf = [0u8; 64];
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"\x1B7\x1B[94m\x1B8\x1B[94m");
+
+    term.enable_alt().unwrap();
+    term.set_fg(Color::Red).unwrap();
+    term.disable_alt().unwrap();
+    term.set_fg(Color::Red).unwrap();
+    term.flush().unwrap();
+    let mut out = Vec::new();
+    let expected: &[u8] =
+        b"\x1B[?1049h\x1B[91m\x1B[?1049l\x1B[91m";
+    while out.len() < expected.len() {
+        let n = master.read(&mut buf).unwrap();
+        out.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(out, expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_link_closed_by_reset() {
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+
+    term.begin_link("https://x.io", None).unwrap();
+    term.prints("x").unwrap();
+    term.prints(".io").unwrap();
+    term.reset_styles().unwrap();
+    term.reset_styles().unwrap();
+    term.flush().unwrap();
+
+    let expected: &[u8] =
+        b"\x1B]8;;https://x.io\x1B\\x.io\x1B]8;;\x1B\\\x1B[0m\x1B[0m";
+    let mut buf = [0u8; 64];
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_palette_restored_on_close() {
+    use std::io::{ Read, Write };
+    use std::os::unix::io::AsRawFd;
+    use crate::common::enums::{ Color, ColorSlot };
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+
+    // The background color, then the DA1 sentinel.
+    master.write_all(b"\x1B]11;rgb:ffff/ffff/ffff\x1B\\\x1B[?62c").unwrap();
+    let luminance = term.background_luminance().unwrap();
+    assert!((luminance - 1.0).abs() < 1e-9);
+    // Mid grey (#777777, luminance 0.18) is still light enough for black.
+    master.write_all(b"\x1B]11;rgb:7777/7777/7777\x1B\\\x1B[?62c").unwrap();
+    assert!(!term.is_dark_background().unwrap());
+    master.write_all(b"\x1B]11;rgb:7070/7070/7070\x1B\\\x1B[?62c").unwrap();
+    assert!(term.is_dark_background().unwrap());
+
+    // The first change to a slot asks for its color, so that `close` can
+    // put it back. The foreground goes unanswered and is reset instead. The
+    // questions skip the output buffer, so they go out before the changes.
+    master.write_all(b"\x1B]4;3;rgb:cdcd/cdcd/0000\x1B\\\x1B[?62c").unwrap();
+    term.set_color(ColorSlot::Palette(3), Color::Black).unwrap();
+    term.set_color(ColorSlot::Palette(3), Color::White).unwrap();
+    master.write_all(b"\x1B[?62c").unwrap();
+    term.set_color(ColorSlot::Foreground, Color::Black).unwrap();
+    master.write_all(b"\x1B[?62c").unwrap();
+    term.set_color(ColorSlot::Background, Color::Black).unwrap();
+    term.reset_color(ColorSlot::Background).unwrap();
+    term.close().unwrap();
+
+    let expected: &[u8] = b"\x1B]11;?\x1B\\\x1B[c\
+        \x1B]11;?\x1B\\\x1B[c\x1B]11;?\x1B\\\x1B[c\
+        \x1B]4;3;?\x1B\\\x1B[c\x1B]10;?\x1B\\\x1B[c\x1B]11;?\x1B\\\x1B[c\
+        \x1B]4;3;rgb:00/00/00\x1B\\\x1B]4;3;rgb:ff/ff/ff\x1B\\\
+        \x1B]10;rgb:00/00/00\x1B\\\x1B]11;rgb:00/00/00\x1B\\\
+        \x1B]111\x1B\\\x1B[?1049l\x1B[?1006l\x1B[?1015l\x1B[?1002l\
+        \x1B[?1000l\x1B[?25h\x1B]4;3;rgb:cd/cd/00\x1B\\\
+        \x1B]110\x1B\\\x1B[0m";
+    let mut out = Vec::new();
+    let mut buf = [0u8; 256];
+    while out.len() < expected.len() {
+        let n = master.read(&mut buf).unwrap();
+        out.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(String::from_utf8_lossy(&out),
+        String::from_utf8_lossy(expected));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_blink_restored_on_close() {
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.enable_blink().unwrap();
+    term.disable_blink().unwrap();
+    term.enable_blink().unwrap();
+    term.close().unwrap();
+
+    let expected: &[u8] = b"\x1B[?12h\x1B[?12l\x1B[?12h\x1B[?1049l\
+        \x1B[?1006l\x1B[?1015l\x1B[?1002l\x1B[?1000l\x1B[?25h\x1B[?12l\
+        \x1B[0m";
+    let mut out = Vec::new();
+    let mut buf = [0u8; 256];
+    while out.len() < expected.len() {
+        let n = master.read(&mut buf).unwrap();
+        out.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(out, expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scroll_and_edit_counts() {
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+    term.scroll_up(0).unwrap();
+    term.scroll_down(i16::MIN).unwrap();
+    term.set_scroll_region(i16::MIN, i16::MAX).unwrap_err();
+    term.insert_lines(0).unwrap();
+    term.erase_chars(0).unwrap();
+    term.delete_chars(i16::MIN).unwrap();
+    term.flush().unwrap();
+
+    let mut buf = [0u8; 64];
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"\x1B[32767T\x1B[32767P");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_capacity_raises_auto_flush() {
+    use std::io::Read;
+    use std::os::unix::io::AsRawFd;
+    use super::posix::Term;
+
+    let (mut master, slave) = open_pty();
+    let term = Term::from_fd(slave.as_raw_fd()).unwrap();
+    term.raw().unwrap();
+    term.set_auto_flush(8).unwrap();
+    term.set_capacity(32).unwrap();
+    term.prints(&"x".repeat(20)).unwrap();
+
+    let mut fd = libc::pollfd {
+        fd: master.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    assert_eq!(unsafe { libc::poll(&mut fd, 1, 50) }, 0);
+
+    term.flush().unwrap();
+    let mut buf = [0u8; 64];
+    let n = master.read(&mut buf).unwrap();
+    assert_eq!(n, 20);
+}
@@ -1,6 +1,13 @@
 use std::io::{ Result, Error, ErrorKind };
+use std::sync::{
+    Mutex, MutexGuard,
+    atomic::{ AtomicBool, AtomicUsize, Ordering },
+};
 use crate::actions::{ ansi, wincon };
-use crate::common::enums::{ Clear, Style, Color };
+use crate::common::enums::{
+    Clear, Style, TextStyle, Color, ColorDepth, ColorEncoding,
+    ColorSlot, CursorShape, ClipboardTarget
+};
 use wincon::handle::{ Handle, ConsoleInfo };
 
 
@@ -51,7 +58,53 @@ pub struct Term {
     conout: Handle,
     conin: Handle,
     altout: Option<Handle>,
-    ansi: bool
+    ansi: bool,
+    // The position saved by `mark_pos` when not using ANSI.
+    marked: Mutex<Option<(i16, i16)>>,
+    // The scroll region that was set, for `close` to reset. Without ANSI
+    // the console has no scroll region, so the scrolling functions confine
+    // themselves to it.
+    region: Mutex<Option<(i16, i16)>>,
+    // Whether frames are wrapped in synchronized output brackets. The
+    // console cannot be asked, so they are off until `set_sync`.
+    sync: Mutex<Option<bool>>,
+    // What the functions below that only work with ANSI have changed, for
+    // `close` to undo (see the posix `Term`).
+    has_cursor_style: AtomicBool,
+    restore_blink: Mutex<Option<bool>>,
+    pushed_titles: AtomicUsize,
+    has_link: AtomicBool,
+    changed_colors: Mutex<Vec<ColorSlot>>,
+    // The style that the console has now, as far as this `Term` knows, and
+    // the ones that DECSC and the alternate screen saved.
+    active: Mutex<TextStyle>,
+    marked_style: Mutex<TextStyle>,
+    alt_style: Mutex<TextStyle>,
+    colors: Mutex<Colors>,
+}
+
+// How colors are sent: brought down to `depth` first, then encoded.
+#[derive(Clone, Copy)]
+struct Colors {
+    depth: ColorDepth,
+    encoding: ColorEncoding,
+}
+
+impl Colors {
+    // With VT processing on, the console takes 24-bit colors; without it
+    // there are only the 16 console colors.
+    fn of(ansi: bool) -> Self {
+        match ansi {
+            true => Self {
+                depth: ColorDepth::TrueColor,
+                encoding: ColorEncoding::Indexed,
+            },
+            false => Self {
+                depth: ColorDepth::Ansi16,
+                encoding: ColorEncoding::Legacy,
+            },
+        }
+    }
 }
 
 impl Term {
@@ -62,7 +115,21 @@ impl Term {
         let reset = ConsoleInfo::of(&conout)?.attributes();
         let conin = Handle::conin()?;
         let altout = None;
-        Ok(Self{ mode, reset, conout, conin, altout, ansi })
+        Ok(Self {
+            mode, reset, conout, conin, altout, ansi,
+            marked: Mutex::new(None),
+            region: Mutex::new(None),
+            sync: Mutex::new(None),
+            has_cursor_style: AtomicBool::new(false),
+            restore_blink: Mutex::new(None),
+            pushed_titles: AtomicUsize::new(0),
+            has_link: AtomicBool::new(false),
+            changed_colors: Mutex::new(Vec::new()),
+            active: Mutex::new(TextStyle::default()),
+            marked_style: Mutex::new(TextStyle::default()),
+            alt_style: Mutex::new(TextStyle::default()),
+            colors: Mutex::new(Colors::of(ansi)),
+        })
     }
 
     // CURSOR FUNCTIONS    
@@ -122,6 +189,37 @@ impl Term {
         wincon::cursor::pos(&self.conout)
     }
 
+    pub fn mark_pos(&self) -> Result<()> {
+        if self.ansi {
+            self.save_style(&self.marked_style)?;
+            ansi::output::prints(&ansi::cursor::mark_pos())?;
+            return Ok(())
+        }
+        let pos = wincon::cursor::pos(&self.conout)?;
+        match self.marked.lock() {
+            Ok(mut marked) => { *marked = Some(pos); Ok(()) }
+            Err(_) => Err(Error::new(ErrorKind::Other,
+                "The marked position was poisoned by a panic.")),
+        }
+    }
+
+    pub fn load_pos(&self) -> Result<()> {
+        if self.ansi {
+            self.load_style(&self.marked_style)?;
+            ansi::output::prints(&ansi::cursor::load_pos())?;
+            return Ok(())
+        }
+        let marked = match self.marked.lock() {
+            Ok(marked) => *marked,
+            Err(_) => None,
+        };
+        match marked {
+            Some((col, row)) => wincon::cursor::goto(col, row, &self.conout),
+            None => Err(Error::new(
+                ErrorKind::NotFound, "No cursor position was marked.")),
+        }
+    }
+
     pub fn hide_cursor(&self) -> Result<()> {
         if self.ansi { ansi::output::prints(
             &ansi::cursor::hide_cursor())?; return Ok(()) }
@@ -136,6 +234,42 @@ impl Term {
         wincon::cursor::show_cursor(&self.conout)
     }
 
+    // The console has no cursor shapes, blinking or cursor colors of its
+    // own: without ANSI these do nothing.
+    pub fn set_cursor_shape(&self, shape: CursorShape) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        self.has_cursor_style.store(true, Ordering::SeqCst);
+        ansi::output::prints(&ansi::cursor::set_shape(shape))
+    }
+
+    pub fn enable_blink(&self) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        self.restore_blink()?.get_or_insert(false);
+        ansi::output::prints(&ansi::cursor::enable_blink())
+    }
+
+    pub fn disable_blink(&self) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        self.restore_blink()?.get_or_insert(true);
+        ansi::output::prints(&ansi::cursor::disable_blink())
+    }
+
+    fn restore_blink(&self) -> Result<MutexGuard<'_, Option<bool>>> {
+        self.restore_blink.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The blink setting was poisoned by a panic."))
+    }
+
+    pub fn set_cursor_color(&self, color: Color) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        self.has_cursor_style.store(true, Ordering::SeqCst);
+        ansi::output::prints(&ansi::cursor::set_color(color))
+    }
+
+    pub fn reset_cursor_color(&self) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        ansi::output::prints(&ansi::cursor::reset_color())
+    }
+
     // SCREEN FUNCTIONS
     pub fn clear(&self, method: Clear) -> Result<()> {
         if self.ansi { ansi::output::prints(
@@ -156,7 +290,136 @@ impl Term {
         wincon::screen::resize(w, h, &self.conout)
     }
     
+    // Confines scrolling to the rows from `top` to `bottom` (inclusive).
+    // The cursor moves to the top left corner of the screen.
+    pub fn set_scroll_region(&self, top: i16, bottom: i16) -> Result<()> {
+        let (top, bottom) = (top.saturating_abs(), bottom.saturating_abs());
+        if top >= bottom {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                "The scroll region needs at least two rows."));
+        }
+        *self.region()? = Some((top, bottom));
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::set_scroll_region(top, bottom))?; return Ok(()) }
+        let (_, _, _, row) = ConsoleInfo::of(&self.conout)?.window_pos();
+        wincon::cursor::goto(0, row, &self.conout)
+    }
+
+    pub fn reset_scroll_region(&self) -> Result<()> {
+        *self.region()? = None;
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::reset_scroll_region())?; return Ok(()) }
+        Ok(())
+    }
+
+    pub fn scroll_up(&self, n: i16) -> Result<()> {
+        // Terminals take a count of 0 to mean 1.
+        if n == 0 { return Ok(()) }
+        let n = n.saturating_abs();
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::scroll_up(n))?; return Ok(()) }
+        wincon::screen::scroll_up(n, *self.region()?, &self.conout)
+    }
+
+    pub fn scroll_down(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        let n = n.saturating_abs();
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::scroll_down(n))?; return Ok(()) }
+        wincon::screen::scroll_down(n, *self.region()?, &self.conout)
+    }
+
+    pub fn reverse_index(&self) -> Result<()> {
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::reverse_index())?; return Ok(()) }
+        wincon::screen::reverse_index(*self.region()?, &self.conout)
+    }
+
+    fn region(&self) -> Result<MutexGuard<'_, Option<(i16, i16)>>> {
+        self.region.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The scroll region was poisoned by a panic."))
+    }
+
+    // WINDOW FUNCTIONS
+    // Without ANSI these do nothing.
+    pub fn set_title(&self, title: &str) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        ansi::output::prints(&ansi::screen::set_title(title))
+    }
+
+    pub fn set_icon_name(&self, name: &str) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        ansi::output::prints(&ansi::screen::set_icon_name(name))
+    }
+
+    pub fn set_title_and_icon(&self, title: &str) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        ansi::output::prints(&ansi::screen::set_title_and_icon(title))
+    }
+
+    // Saves the current title and icon name, to be put back by `pop_title`
+    // (or by `close`).
+    pub fn push_title(&self) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        self.pushed_titles.fetch_add(1, Ordering::SeqCst);
+        ansi::output::prints(&ansi::screen::push_title())
+    }
+
+    // Restores the last pushed title and icon name. Does nothing if every
+    // title this `Term` pushed has been popped already.
+    pub fn pop_title(&self) -> Result<()> {
+        let popped = self.pushed_titles.fetch_update(
+            Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        match popped {
+            Ok(_) => ansi::output::prints(&ansi::screen::pop_title()),
+            Err(_) => Ok(()),
+        }
+    }
+
+    // EDITING FUNCTIONS
+    // A count of 0 is skipped; terminals would take it to mean 1.
+    pub fn insert_lines(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        let n = n.saturating_abs();
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::insert_lines(n))?; return Ok(()) }
+        wincon::screen::insert_lines(n, &self.conout)
+    }
+
+    pub fn delete_lines(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        let n = n.saturating_abs();
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::delete_lines(n))?; return Ok(()) }
+        wincon::screen::delete_lines(n, &self.conout)
+    }
+
+    pub fn insert_chars(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        let n = n.saturating_abs();
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::insert_chars(n))?; return Ok(()) }
+        wincon::screen::insert_chars(n, &self.conout)
+    }
+
+    pub fn delete_chars(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        let n = n.saturating_abs();
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::delete_chars(n))?; return Ok(()) }
+        wincon::screen::delete_chars(n, &self.conout)
+    }
+
+    pub fn erase_chars(&self, n: i16) -> Result<()> {
+        if n == 0 { return Ok(()) }
+        let n = n.saturating_abs();
+        if self.ansi { ansi::output::prints(
+            &ansi::screen::erase_chars(n))?; return Ok(()) }
+        wincon::screen::erase_chars(n, &self.conout)
+    }
+
     pub fn enable_alt(&mut self) -> Result<()> {
+        self.save_style(&self.alt_style)?;
         if self.altout.is_none() {
             self.altout = Some(Handle::buffer()?)
         }
@@ -178,6 +441,7 @@ impl Term {
     }
 
     pub fn disable_alt(&mut self) -> Result<()> {
+        self.load_style(&self.alt_style)?;
         if self.ansi { ansi::output::printf(
             &ansi::screen::disable_alt())?; return Ok(()) }
         // let err_msg = "Error switching back to $STDOUT";
@@ -216,6 +480,69 @@ impl Term {
         wincon::output::disable_raw(&self.conout)
     }
 
+    // FRAME FUNCTIONS
+    // Starts a frame. With synchronized output on, nothing written until
+    // `end_frame` is shown before the whole frame is there.
+    pub fn begin_frame(&self) -> Result<()> {
+        match self.supports_sync() {
+            true => ansi::output::prints(&ansi::screen::begin_sync()),
+            false => Ok(()),
+        }
+    }
+
+    // Ends the frame and flushes it.
+    pub fn end_frame(&self) -> Result<()> {
+        if self.supports_sync() {
+            ansi::output::prints(&ansi::screen::end_sync())?;
+        }
+        self.flush()
+    }
+
+    // Draws a frame with `draw` between `begin_frame` and `end_frame`.
+    pub fn frame<F>(&self, draw: F) -> Result<()>
+    where F: FnOnce(&Self) -> Result<()> {
+        self.begin_frame()?;
+        let drawn = draw(self);
+        // Always close the brackets, or the terminal stops repainting until
+        // its own timeout.
+        self.end_frame()?;
+        drawn
+    }
+
+    // Only once turned on with `set_sync`, and only with ANSI.
+    pub fn supports_sync(&self) -> bool {
+        match self.sync.lock() {
+            Ok(sync) => self.ansi && sync.unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    pub fn set_sync(&self, enabled: bool) -> Result<()> {
+        let mut sync = self.sync.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The sync setting was poisoned by a panic."))?;
+        *sync = Some(enabled);
+        Ok(())
+    }
+
+    // CLIPBOARD FUNCTIONS
+    // Copies `content` to the user's clipboard. Needs ANSI. Content longer
+    // than `clipboard::MAX_CONTENT` bytes is refused.
+    pub fn set_clipboard(
+        &self, target: ClipboardTarget, content: &str
+    ) -> Result<()> {
+        if !self.ansi { return Err(unsupported()) }
+        if content.len() > ansi::clipboard::MAX_CONTENT {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                "The content is too large for the clipboard."));
+        }
+        ansi::output::queue(&ansi::clipboard::BeginCopy(target))?;
+        for chunk in content.as_bytes().chunks(ansi::clipboard::CHUNK_SIZE) {
+            ansi::output::queue(&ansi::clipboard::CopyData(chunk))?;
+        }
+        ansi::output::queue(&ansi::clipboard::EndCopy)?;
+        ansi::output::flush()
+    }
+
     // MOUSE FUNCTIONS
     pub fn enable_mouse(&self) -> Result<()> {
         if self.ansi { ansi::output::prints(
@@ -232,41 +559,164 @@ impl Term {
     }
 
     // STYLE FUNCTIONS
+    // Only what differs from the active style is sent.
     pub fn set_fx(&self, effects: u32) -> Result<()> {
-        if self.ansi { ansi::output::prints(
-            &ansi::style::set_style(Style::Fx(effects)))?; return Ok(()) }
-        // let err_msg = "Error setting console text attributes";
-        wincon::style::set_style(Style::Fx(effects), 0, &self.conout)
+        self.restyle(|active| active.with(Style::Fx(effects)))
     }
 
     pub fn set_fg(&self, color: Color) -> Result<()> {
-        if self.ansi { ansi::output::prints(
-            &ansi::style::set_style(Style::Fg(color)))?; return Ok(()) }
-        // let err_msg = "Error setting console foreground";
-        wincon::style::set_style(Style::Fg(color), self.reset, &self.conout)
+        self.restyle(|active| active.with(Style::Fg(color)))
     }
 
     pub fn set_bg(&self, color: Color) -> Result<()> {
-        if self.ansi { ansi::output::prints(
-            &ansi::style::set_style(Style::Bg(color)))?; return Ok(()) }
-        // let err_msg = "Error setting console background";
-        wincon::style::set_style(Style::Bg(color), self.reset, &self.conout)
+        self.restyle(|active| active.with(Style::Bg(color)))
+    }
+
+    // The console has no underline color; underlines
+    // are drawn in the text color.
+    pub fn set_ul_color(&self, color: Color) -> Result<()> {
+        self.restyle(|active| active.with(Style::Ul(color)))
     }
 
     pub fn set_styles(&self, fg: Color, bg: Color, fx: u32) -> Result<()> {
-        if self.ansi { ansi::output::prints(
-            &ansi::style::set_styles(fg, bg, fx))?; return Ok(()) }
-        // let err_msg = "Error setting multiple console styles";
-        wincon::style::set_styles(fg, bg, fx, self.reset, &self.conout)
+        self.restyle(|active| active
+            .with(Style::Fg(fg))
+            .with(Style::Bg(bg))
+            .with(Style::Fx(fx)))
+    }
+
+    // Switches to `style` as a whole, turning off whatever it leaves out.
+    pub fn set_style(&self, style: TextStyle) -> Result<()> {
+        self.restyle(|_| style)
+    }
+
+    pub fn style(&self) -> Result<TextStyle> {
+        Ok(*self.active()?)
     }
 
+    // Always sends the reset. Also closes a hyperlink that is still open.
     pub fn reset_styles(&self) -> Result<()> {
+        self.end_link()?;
+        *self.active()? = TextStyle::default();
         if self.ansi { ansi::output::prints(
             &ansi::style::reset())?; return Ok(()) }
         // let err_msg = "Error unsetting console styles";
         wincon::style::reset(self.reset, &self.conout)
     }
 
+    // Colors set from now on are replaced by the nearest ones the console
+    // can show at `depth`. Starts out at 24-bit color with ANSI, and at the
+    // 16 console colors without.
+    pub fn set_color_depth(&self, depth: ColorDepth) -> Result<()> {
+        self.colors()?.depth = depth;
+        Ok(())
+    }
+
+    pub fn color_depth(&self) -> Result<ColorDepth> {
+        Ok(self.colors()?.depth)
+    }
+
+    // Whether the basic colors are sent as palette entries or with the
+    // classic codes (ANSI only).
+    pub fn set_color_encoding(&self, encoding: ColorEncoding) -> Result<()> {
+        self.colors()?.encoding = encoding;
+        Ok(())
+    }
+
+    pub fn color_encoding(&self) -> Result<ColorEncoding> {
+        Ok(self.colors()?.encoding)
+    }
+
+    // PALETTE FUNCTIONS
+    // Changes a palette entry or default color of the terminal. `close`
+    // gives the terminal its own colors back. Needs ANSI.
+    pub fn set_color(&self, slot: ColorSlot, color: Color) -> Result<()> {
+        if !self.ansi { return Err(unsupported()) }
+        if slot == ColorSlot::Cursor {
+            return self.set_cursor_color(color);
+        }
+        let mut changed = self.changed_colors()?;
+        if !changed.contains(&slot) {
+            changed.push(slot);
+        }
+        ansi::output::prints(&ansi::palette::set_color(slot, color))
+    }
+
+    pub fn reset_color(&self, slot: ColorSlot) -> Result<()> {
+        if !self.ansi { return Err(unsupported()) }
+        self.changed_colors()?.retain(|s| *s != slot);
+        ansi::output::prints(&ansi::palette::reset_color(slot))
+    }
+
+    fn changed_colors(&self) -> Result<MutexGuard<'_, Vec<ColorSlot>>> {
+        self.changed_colors.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The changed colors were poisoned by a panic."))
+    }
+
+    fn colors(&self) -> Result<MutexGuard<'_, Colors>> {
+        self.colors.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The color settings were poisoned by a panic."))
+    }
+
+    fn restyle<F>(&self, f: F) -> Result<()>
+    where F: FnOnce(TextStyle) -> TextStyle {
+        let Colors { depth, encoding } = *self.colors()?;
+        let mut active = self.active()?;
+        let next = f(*active);
+        let next = TextStyle {
+            fg: next.fg.downsample(depth),
+            bg: next.bg.downsample(depth),
+            ul: next.ul.downsample(depth),
+            ..next
+        };
+        if self.ansi {
+            ansi::output::prints(
+                &ansi::style::transition(*active, next, encoding))?;
+        } else if next != *active {
+            // let err_msg = "Error setting console text attributes";
+            wincon::style::set_styles(
+                next.fg, next.bg, next.fx, self.reset, &self.conout)?;
+        }
+        *active = next;
+        Ok(())
+    }
+
+    // Only track what the terminal does by itself when it saves and
+    // restores the cursor; nothing is sent.
+    fn save_style(&self, saved: &Mutex<TextStyle>) -> Result<()> {
+        let active = *self.active()?;
+        *saved.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The saved style was poisoned by a panic."))? = active;
+        Ok(())
+    }
+
+    fn load_style(&self, saved: &Mutex<TextStyle>) -> Result<()> {
+        let style = *saved.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The saved style was poisoned by a panic."))?;
+        *self.active()? = style;
+        Ok(())
+    }
+
+    fn active(&self) -> Result<MutexGuard<'_, TextStyle>> {
+        self.active.lock().map_err(|_| Error::new(ErrorKind::Other,
+            "The active style was poisoned by a panic."))
+    }
+
+    // Makes everything printed until `end_link` a hyperlink to `url`.
+    // Without ANSI the text is printed as it is.
+    pub fn begin_link(&self, url: &str, id: Option<&str>) -> Result<()> {
+        if !self.ansi { return Ok(()) }
+        self.has_link.store(true, Ordering::SeqCst);
+        ansi::output::prints(&ansi::style::begin_link(url, id))
+    }
+
+    pub fn end_link(&self) -> Result<()> {
+        match self.has_link.swap(false, Ordering::SeqCst) {
+            true => ansi::output::prints(&ansi::style::end_link()),
+            false => Ok(()),
+        }
+    }
+
     // CONFIG FUNCTIONS
     pub fn get_mode(&self) -> Result<u32> {
         wincon::output::get_mode()
@@ -298,11 +748,32 @@ impl Term {
 
     pub fn close(&mut self) -> Result<()> {
         // Revert back to original settings.
+        if self.region()?.is_some() { self.reset_scroll_region()? }
         self.disable_alt()?;
         self.conout.set_mode(&self.mode)?;
         self.disable_mouse()?;
         self.show_cursor()?;
+        // Only set with ANSI on.
+        if self.has_cursor_style.swap(false, Ordering::SeqCst) {
+            ansi::output::prints(
+                &ansi::cursor::set_shape(CursorShape::Default))?;
+            ansi::output::prints(&ansi::cursor::reset_color())?;
+        }
+        match self.restore_blink()?.take() {
+            Some(true) => ansi::output::prints(
+                &ansi::cursor::enable_blink())?,
+            Some(false) => ansi::output::prints(
+                &ansi::cursor::disable_blink())?,
+            None => (),
+        }
+        for slot in self.changed_colors()?.drain(..) {
+            ansi::output::prints(&ansi::palette::reset_color(slot))?;
+        }
+        for _ in 0..self.pushed_titles.swap(0, Ordering::SeqCst) {
+            ansi::output::prints(&ansi::screen::pop_title())?;
+        }
         self.reset_styles()?;
+        self.flush()?;
         // Clean up Handles.
         self.conout.close()?; 
         self.conin.close()?;
@@ -318,4 +789,10 @@ impl Drop for Term {
     fn drop(&mut self) {
         self.close().expect("Error closing the terminal.");
     }
-}
\ No newline at end of file
+}
+
+
+fn unsupported() -> Error {
+    Error::new(ErrorKind::Unsupported,
+        "The console needs ANSI (VT) processing for this.")
+}